@@ -0,0 +1,513 @@
+//!
+//! Airdrop in Stylus Rust
+//!
+//! A smart contract holding pull-based airdrops: a creator deposits the tokens
+//! along with the Merkle root of the `(index, account, amount)` entries, and
+//! every entry is claimed with a proof until the airdrop's deadline
+//!
+//! Split out of YourContract to keep both under the Stylus size limit, it takes
+//! no protocol fee and has no owner, so nobody can pause claims or move the funds
+//!
+
+// Allow `cargo stylus export-abi` to generate a main function.
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+pub mod merkle;
+
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::{
+    alloy_primitives::{Address, B256, U256},
+    alloy_sol_types::sol,
+    prelude::*,
+    stylus_core::log,
+};
+
+/// Import the building blocks shared with YourContract
+use common::{
+    reentrancy_guard::{self, NonReentrant, ReentrancyGuard},
+    transfers::{
+        self, Erc20CallFailed, Erc20InvalidReturnData, Erc20TransferReturnedFalse,
+        InsufficientValue, PayoutFailed, RefundFailed,
+    },
+};
+
+/// Import OpenZeppelin BitMap, used to track claimed airdrop entries
+use openzeppelin_stylus::utils::structs::bitmap::BitMap;
+
+// Define errors
+sol! {
+    #[derive(Debug)]
+    error InvalidDeadline(uint256 deadline);
+    #[derive(Debug)]
+    error EmptyAirdrop();
+    #[derive(Debug)]
+    error AirdropNotFound(uint256 airdropId);
+    #[derive(Debug)]
+    error AirdropExpired(uint256 airdropId);
+    #[derive(Debug)]
+    error AirdropNotExpired(uint256 airdropId);
+    #[derive(Debug)]
+    error AirdropAlreadyClaimed(uint256 airdropId, uint256 index);
+    #[derive(Debug)]
+    error InvalidMerkleProof(uint256 airdropId, uint256 index);
+    #[derive(Debug)]
+    error AirdropExhausted(uint256 airdropId, uint256 remaining, uint256 amount);
+    #[derive(Debug)]
+    error NotAirdropCreator(uint256 airdropId, address account);
+    #[derive(Debug)]
+    error NothingToReclaim(uint256 airdropId);
+}
+
+/// Error types for the contract
+#[derive(SolidityError, Debug)]
+pub enum Error {
+    InsufficientValue(InsufficientValue),
+    RefundFailed(RefundFailed),
+    PayoutFailed(PayoutFailed),
+    Erc20CallFailed(Erc20CallFailed),
+    Erc20TransferReturnedFalse(Erc20TransferReturnedFalse),
+    Erc20InvalidReturnData(Erc20InvalidReturnData),
+    InvalidDeadline(InvalidDeadline),
+    EmptyAirdrop(EmptyAirdrop),
+    AirdropNotFound(AirdropNotFound),
+    AirdropExpired(AirdropExpired),
+    AirdropNotExpired(AirdropNotExpired),
+    AirdropAlreadyClaimed(AirdropAlreadyClaimed),
+    InvalidMerkleProof(InvalidMerkleProof),
+    AirdropExhausted(AirdropExhausted),
+    NotAirdropCreator(NotAirdropCreator),
+    NothingToReclaim(NothingToReclaim),
+    ReentrantCall(reentrancy_guard::ReentrancyGuardReentrantCall),
+}
+
+impl From<transfers::Error> for Error {
+    fn from(value: transfers::Error) -> Self {
+        match value {
+            transfers::Error::InsufficientValue(e) => Error::InsufficientValue(e),
+            transfers::Error::RefundFailed(e) => Error::RefundFailed(e),
+            transfers::Error::PayoutFailed(e) => Error::PayoutFailed(e),
+            transfers::Error::Erc20CallFailed(e) => Error::Erc20CallFailed(e),
+            transfers::Error::Erc20TransferReturnedFalse(e) => Error::Erc20TransferReturnedFalse(e),
+            transfers::Error::Erc20InvalidReturnData(e) => Error::Erc20InvalidReturnData(e),
+        }
+    }
+}
+
+impl From<reentrancy_guard::Error> for Error {
+    fn from(value: reentrancy_guard::Error) -> Self {
+        match value {
+            reentrancy_guard::Error::ReentrantCall(e) => Error::ReentrantCall(e),
+        }
+    }
+}
+
+// Define events
+sol! {
+    event AirdropCreated(uint256 indexed airdropId, address indexed token, address indexed creator, bytes32 merkleRoot, uint256 totalAmount, uint256 deadline);
+    event AirdropClaimed(uint256 indexed airdropId, uint256 index, address indexed account, uint256 amount);
+    event AirdropReclaimed(uint256 indexed airdropId, address indexed creator, uint256 amount);
+}
+
+// Define persistent storage using the Solidity ABI.
+// `Airdrops` will be the entrypoint.
+sol_storage! {
+    #[entrypoint]
+    pub struct Airdrops {
+        ReentrancyGuard reentrancy_guard;
+        uint256 airdrop_count;
+        mapping(uint256 => Airdrop) airdrops;
+        mapping(uint256 => BitMap) airdrop_claimed;
+    }
+
+    // Pull-based airdrop funded up front, see `create_airdrop`
+    pub struct Airdrop {
+        address token;
+        address creator;
+        bytes32 merkle_root;
+        uint256 remaining;
+        uint256 deadline;
+    }
+}
+
+/// Declare that `Airdrops` is a contract with the following external methods.
+#[public]
+impl Airdrops {
+    /// Create a pull-based airdrop of `total_amount` of `token`, the zero address stands
+    /// for native ETH, and return its id
+    /// `merkle_root` commits to `(uint256 index, address account, uint256 amount)` leaves
+    /// built like OpenZeppelin's `StandardMerkleTree`, recipients pull with `claim_airdrop`
+    /// until `deadline`, after which the creator can take back the rest with `reclaim_airdrop`
+    /// Native airdrops are funded with `msg.value`, any surplus is refunded to the sender,
+    /// as is any `msg.value` sent along with an ERC-20 airdrop
+    /// Note: User must approve this contract to spend ERC-20 tokens before calling
+    #[payable]
+    pub fn create_airdrop(
+        &mut self,
+        token: Address,
+        merkle_root: B256,
+        total_amount: U256,
+        deadline: U256,
+    ) -> Result<U256, Error> {
+        self.non_reentrant(|this| this.new_airdrop(token, merkle_root, total_amount, deadline))
+    }
+
+    /// Pay out entry `index` of airdrop `airdrop_id` to `account`
+    /// Anyone may submit the claim, the funds always go to `account`
+    pub fn claim_airdrop(
+        &mut self,
+        airdrop_id: U256,
+        index: U256,
+        account: Address,
+        amount: U256,
+        proof: Vec<B256>,
+    ) -> Result<(), Error> {
+        self.non_reentrant(|this| {
+            this.pay_airdrop_claim(airdrop_id, index, account, amount, &proof)
+        })
+    }
+
+    /// Return whatever is left of airdrop `airdrop_id` to its creator once its deadline passed
+    /// Reverts with `NothingToReclaim` once everything was claimed or reclaimed
+    pub fn reclaim_airdrop(&mut self, airdrop_id: U256) -> Result<(), Error> {
+        self.non_reentrant(|this| {
+            this.require_airdrop(airdrop_id)?;
+            let (token, creator, _, remaining, _) = this.airdrop(airdrop_id);
+            let caller = this.vm().msg_sender();
+            if caller != creator {
+                return Err(Error::NotAirdropCreator(NotAirdropCreator {
+                    airdropId: airdrop_id,
+                    account: caller,
+                }));
+            }
+            if !this.airdrop_expired(airdrop_id) {
+                return Err(Error::AirdropNotExpired(AirdropNotExpired {
+                    airdropId: airdrop_id,
+                }));
+            }
+            if remaining.is_zero() {
+                return Err(Error::NothingToReclaim(NothingToReclaim {
+                    airdropId: airdrop_id,
+                }));
+            }
+
+            this.airdrops.setter(airdrop_id).remaining.set(U256::ZERO);
+            transfers::pay_out(this.vm(), token, creator, remaining)?;
+
+            log(
+                this.vm(),
+                AirdropReclaimed {
+                    airdropId: airdrop_id,
+                    creator,
+                    amount: remaining,
+                },
+            );
+
+            Ok(())
+        })
+    }
+
+    /// Get airdrop `airdrop_id` as `(token, creator, merkle_root, remaining, deadline)`
+    pub fn airdrop(&self, airdrop_id: U256) -> (Address, Address, B256, U256, U256) {
+        let airdrop = self.airdrops.getter(airdrop_id);
+        (
+            airdrop.token.get(),
+            airdrop.creator.get(),
+            airdrop.merkle_root.get(),
+            airdrop.remaining.get(),
+            airdrop.deadline.get(),
+        )
+    }
+
+    /// Get the number of airdrops created so far, ids run from 1 to this value
+    pub fn airdrop_count(&self) -> U256 {
+        self.airdrop_count.get()
+    }
+
+    /// Check whether entry `index` of airdrop `airdrop_id` was claimed
+    pub fn is_airdrop_claimed(&self, airdrop_id: U256, index: U256) -> bool {
+        self.airdrop_claimed.getter(airdrop_id).get(index)
+    }
+}
+
+/// Internal helpers, not exposed in the ABI
+impl Airdrops {
+    /// Fund a new airdrop for `create_airdrop`
+    fn new_airdrop(
+        &mut self,
+        token: Address,
+        merkle_root: B256,
+        total_amount: U256,
+        deadline: U256,
+    ) -> Result<U256, Error> {
+        if total_amount.is_zero() {
+            return Err(Error::EmptyAirdrop(EmptyAirdrop {}));
+        }
+        if deadline <= U256::from(self.vm().block_timestamp()) {
+            return Err(Error::InvalidDeadline(InvalidDeadline { deadline }));
+        }
+
+        let sender = self.vm().msg_sender();
+        transfers::deposit(self.vm(), token, sender, total_amount)?;
+
+        let airdrop_id = self.airdrop_count.get() + U256::from(1);
+        self.airdrop_count.set(airdrop_id);
+        let mut airdrop = self.airdrops.setter(airdrop_id);
+        airdrop.token.set(token);
+        airdrop.creator.set(sender);
+        airdrop.merkle_root.set(merkle_root);
+        airdrop.remaining.set(total_amount);
+        airdrop.deadline.set(deadline);
+
+        log(
+            self.vm(),
+            AirdropCreated {
+                airdropId: airdrop_id,
+                token,
+                creator: sender,
+                merkleRoot: merkle_root,
+                totalAmount: total_amount,
+                deadline,
+            },
+        );
+
+        Ok(airdrop_id)
+    }
+
+    /// Check and pay out one airdrop entry for `claim_airdrop`
+    fn pay_airdrop_claim(
+        &mut self,
+        airdrop_id: U256,
+        index: U256,
+        account: Address,
+        amount: U256,
+        proof: &[B256],
+    ) -> Result<(), Error> {
+        self.require_airdrop(airdrop_id)?;
+        let (token, _, merkle_root, remaining, _) = self.airdrop(airdrop_id);
+        if self.airdrop_expired(airdrop_id) {
+            return Err(Error::AirdropExpired(AirdropExpired {
+                airdropId: airdrop_id,
+            }));
+        }
+        if self.is_airdrop_claimed(airdrop_id, index) {
+            return Err(Error::AirdropAlreadyClaimed(AirdropAlreadyClaimed {
+                airdropId: airdrop_id,
+                index,
+            }));
+        }
+        let leaf = merkle::airdrop_leaf(index, account, amount);
+        if !merkle::verify(proof, merkle_root, leaf) {
+            return Err(Error::InvalidMerkleProof(InvalidMerkleProof {
+                airdropId: airdrop_id,
+                index,
+            }));
+        }
+        // Only reachable if the tree adds up to more than was deposited
+        if amount > remaining {
+            return Err(Error::AirdropExhausted(AirdropExhausted {
+                airdropId: airdrop_id,
+                remaining,
+                amount,
+            }));
+        }
+
+        self.airdrop_claimed.setter(airdrop_id).set(index);
+        self.airdrops
+            .setter(airdrop_id)
+            .remaining
+            .set(remaining - amount);
+        transfers::pay_out(self.vm(), token, account, amount)?;
+
+        log(
+            self.vm(),
+            AirdropClaimed {
+                airdropId: airdrop_id,
+                index,
+                account,
+                amount,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Revert if airdrop `airdrop_id` was never created
+    fn require_airdrop(&self, airdrop_id: U256) -> Result<(), Error> {
+        if self.airdrops.getter(airdrop_id).creator.get().is_zero() {
+            return Err(Error::AirdropNotFound(AirdropNotFound {
+                airdropId: airdrop_id,
+            }));
+        }
+        Ok(())
+    }
+
+    /// Whether the claim window of airdrop `airdrop_id` is over
+    fn airdrop_expired(&self, airdrop_id: U256) -> bool {
+        U256::from(self.vm().block_timestamp()) > self.airdrops.getter(airdrop_id).deadline.get()
+    }
+}
+
+impl NonReentrant for Airdrops {
+    fn reentrancy_guard(&mut self) -> &mut ReentrancyGuard {
+        &mut self.reentrancy_guard
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Error;
+    use super::*;
+    use stylus_sdk::testing::*;
+
+    /// Simulate a call carrying `value` wei, crediting it to the contract like the EVM would
+    fn fund_call(vm: &TestVM, value: U256) {
+        vm.set_value(value);
+        vm.set_balance(
+            vm.contract_address(),
+            vm.balance(vm.contract_address()) + value,
+        );
+    }
+
+    #[test]
+    fn test_merkle_airdrop() {
+        let vm = TestVM::default();
+        let mut contract = Airdrops::from(&vm);
+
+        let alice = Address::from([2u8; 20]);
+        let bob = Address::from([3u8; 20]);
+        let token = Address::from([9u8; 20]);
+        vm.set_code(token, vec![0x00]);
+        let creator = vm.msg_sender();
+        vm.set_block_timestamp(1_000);
+
+        let alice_leaf = merkle::airdrop_leaf(U256::from(0), alice, U256::from(60));
+        let bob_leaf = merkle::airdrop_leaf(U256::from(1), bob, U256::from(30));
+        let root = merkle::hash_pair(alice_leaf, bob_leaf);
+        let deadline = U256::from(2_000);
+
+        let err = contract
+            .create_airdrop(Address::ZERO, root, U256::ZERO, deadline)
+            .unwrap_err();
+        assert!(matches!(err, Error::EmptyAirdrop(_)));
+        let err = contract
+            .create_airdrop(Address::ZERO, root, U256::from(100), U256::from(1_000))
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidDeadline(_)));
+
+        // Native airdrops are funded with msg.value
+        vm.set_value(U256::from(99));
+        let err = contract
+            .create_airdrop(Address::ZERO, root, U256::from(100), deadline)
+            .unwrap_err();
+        assert!(matches!(err, Error::InsufficientValue(_)));
+        fund_call(&vm, U256::from(100));
+        let id = contract
+            .create_airdrop(Address::ZERO, root, U256::from(100), deadline)
+            .unwrap();
+        assert_eq!(id, U256::from(1));
+        assert_eq!(contract.airdrop_count(), U256::from(1));
+        assert_eq!(
+            contract.airdrop(id),
+            (Address::ZERO, creator, root, U256::from(100), deadline)
+        );
+        vm.set_value(U256::ZERO);
+
+        // Anyone can submit a claim, the funds go to the listed account
+        let err = contract
+            .claim_airdrop(id, U256::from(0), alice, U256::from(61), vec![bob_leaf])
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidMerkleProof(_)));
+        vm.set_sender(bob);
+        contract
+            .claim_airdrop(id, U256::from(0), alice, U256::from(60), vec![bob_leaf])
+            .unwrap();
+        vm.set_sender(creator);
+        assert_eq!(vm.balance(alice), U256::from(60));
+        assert!(contract.is_airdrop_claimed(id, U256::from(0)));
+        assert!(!contract.is_airdrop_claimed(id, U256::from(1)));
+        assert_eq!(contract.airdrop(id).3, U256::from(40));
+        let err = contract
+            .claim_airdrop(id, U256::from(0), alice, U256::from(60), vec![bob_leaf])
+            .unwrap_err();
+        assert!(matches!(err, Error::AirdropAlreadyClaimed(_)));
+
+        let err = contract
+            .claim_airdrop(U256::from(7), U256::from(1), bob, U256::from(30), vec![])
+            .unwrap_err();
+        assert!(matches!(err, Error::AirdropNotFound(_)));
+
+        // Leftovers stay locked until the deadline passes
+        let err = contract.reclaim_airdrop(id).unwrap_err();
+        assert!(matches!(err, Error::AirdropNotExpired(_)));
+        vm.set_block_timestamp(2_001);
+        let err = contract
+            .claim_airdrop(id, U256::from(1), bob, U256::from(30), vec![alice_leaf])
+            .unwrap_err();
+        assert!(matches!(err, Error::AirdropExpired(_)));
+        vm.set_sender(bob);
+        let err = contract.reclaim_airdrop(id).unwrap_err();
+        assert!(matches!(err, Error::NotAirdropCreator(_)));
+        vm.set_sender(creator);
+        contract.reclaim_airdrop(id).unwrap();
+        assert_eq!(vm.balance(creator), U256::from(40));
+        assert_eq!(vm.balance(vm.contract_address()), U256::ZERO);
+        assert_eq!(contract.airdrop(id).3, U256::ZERO);
+        let err = contract.reclaim_airdrop(id).unwrap_err();
+        assert!(matches!(err, Error::NothingToReclaim(_)));
+
+        // ERC-20 airdrops pull the deposit, value sent along by mistake is refunded
+        vm.set_block_timestamp(1_000);
+        fund_call(&vm, U256::from(50));
+        let id = contract
+            .create_airdrop(token, root, U256::from(90), deadline)
+            .unwrap();
+        assert_eq!(id, U256::from(2));
+        assert_eq!(vm.balance(creator), U256::from(90));
+        assert_eq!(vm.balance(vm.contract_address()), U256::ZERO);
+        vm.set_value(U256::ZERO);
+
+        contract
+            .claim_airdrop(id, U256::from(1), bob, U256::from(30), vec![alice_leaf])
+            .unwrap();
+        assert_eq!(contract.airdrop(id).3, U256::from(60));
+    }
+
+    #[test]
+    fn test_reentrancy_guard() {
+        let vm = TestVM::default();
+        let mut contract = Airdrops::from(&vm);
+        let alice = Address::from([2u8; 20]);
+        vm.set_block_timestamp(1_000);
+
+        // TestVM settles `transfer_eth` and mocked calls without running any recipient
+        // code, so a recipient cannot call back into the contract here. Holding the guard
+        // reproduces the state such a callback observes mid-transfer, and every entrypoint
+        // it could reach must reject it before moving funds
+        contract.reentrancy_guard.enter().unwrap();
+        fund_call(&vm, U256::from(10));
+        let err = contract
+            .create_airdrop(Address::ZERO, B256::ZERO, U256::from(10), U256::from(2_000))
+            .unwrap_err();
+        assert!(matches!(err, Error::ReentrantCall(_)));
+        let err = contract
+            .claim_airdrop(U256::from(1), U256::ZERO, alice, U256::from(10), vec![])
+            .unwrap_err();
+        assert!(matches!(err, Error::ReentrantCall(_)));
+        let err = contract.reclaim_airdrop(U256::from(1)).unwrap_err();
+        assert!(matches!(err, Error::ReentrantCall(_)));
+
+        // The guard is released after every call, successful or not
+        contract.reentrancy_guard.exit();
+        let id = contract
+            .create_airdrop(Address::ZERO, B256::ZERO, U256::from(10), U256::from(2_000))
+            .unwrap();
+        assert!(!contract.reentrancy_guard.entered());
+        contract.reclaim_airdrop(id).unwrap_err();
+        assert!(!contract.reentrancy_guard.entered());
+    }
+}
@@ -0,0 +1,72 @@
+//!
+//! Merkle proofs in Stylus Rust
+//!
+//! Helpers to check that an airdrop entry belongs to a Merkle tree, compatible
+//! with trees built by OpenZeppelin's `StandardMerkleTree` for the leaf type
+//! `(uint256 index, address account, uint256 amount)`
+//!
+//! Pairs are hashed in sorted order, so proofs carry no left/right flags
+//!
+
+use stylus_sdk::{
+    alloy_primitives::{Address, B256, U256},
+    alloy_sol_types::SolValue,
+    crypto::keccak,
+};
+
+/// Leaf of an airdrop entry, `keccak256(bytes.concat(keccak256(abi.encode(index, account, amount))))`
+/// Hashing twice keeps a leaf from ever being mistaken for an inner node
+pub fn airdrop_leaf(index: U256, account: Address, amount: U256) -> B256 {
+    keccak(keccak((index, account, amount).abi_encode()))
+}
+
+/// Whether `proof` links `leaf` to `root`
+pub fn verify(proof: &[B256], root: B256, leaf: B256) -> bool {
+    proof
+        .iter()
+        .fold(leaf, |node, sibling| hash_pair(node, *sibling))
+        == root
+}
+
+/// Hash two nodes in sorted order, the parent of `a` and `b` in the tree
+pub fn hash_pair(a: B256, b: B256) -> B256 {
+    let (low, high) = if a <= b { (a, b) } else { (b, a) };
+    let mut data = [0u8; 64];
+    data[..32].copy_from_slice(low.as_slice());
+    data[32..].copy_from_slice(high.as_slice());
+    keccak(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let alice = Address::from([2u8; 20]);
+        let bob = Address::from([3u8; 20]);
+        let carol = Address::from([4u8; 20]);
+        let leaves = [
+            airdrop_leaf(U256::from(0), alice, U256::from(10)),
+            airdrop_leaf(U256::from(1), bob, U256::from(20)),
+            airdrop_leaf(U256::from(2), carol, U256::from(30)),
+        ];
+
+        // A three leaf tree: the third leaf is paired with the root of the first two
+        let left = hash_pair(leaves[0], leaves[1]);
+        let root = hash_pair(left, leaves[2]);
+
+        assert!(verify(&[leaves[1], leaves[2]], root, leaves[0]));
+        assert!(verify(&[leaves[0], leaves[2]], root, leaves[1]));
+        assert!(verify(&[left], root, leaves[2]));
+
+        // A changed entry or a truncated proof no longer reaches the root
+        let forged = airdrop_leaf(U256::from(0), alice, U256::from(11));
+        assert!(!verify(&[leaves[1], leaves[2]], root, forged));
+        assert!(!verify(&[leaves[1]], root, leaves[0]));
+        assert!(!verify(&[leaves[1], leaves[2]], root, leaves[2]));
+
+        // A single leaf tree is its own root
+        assert!(verify(&[], leaves[0], leaves[0]));
+    }
+}
@@ -0,0 +1,128 @@
+//!
+//! Batch checks in Stylus Rust
+//!
+//! Validation shared by every entrypoint taking parallel `recipients` and
+//! `amounts` lists, errors point at the offending entry by its index
+//!
+
+use stylus_sdk::{
+    alloy_primitives::{Address, U256},
+    alloy_sol_types::sol,
+    prelude::*,
+};
+
+/// Batch size limit a contract starts out with
+pub const DEFAULT_MAX_BATCH_SIZE: u64 = 500;
+
+sol! {
+    #[derive(Debug)]
+    error LengthMismatch(uint256 recipientsLength, uint256 amountsLength);
+    #[derive(Debug)]
+    error EmptyBatch();
+    #[derive(Debug)]
+    error BatchTooLarge(uint256 size, uint256 maxSize);
+    #[derive(Debug)]
+    error ZeroRecipient(uint256 index);
+    #[derive(Debug)]
+    error SelfRecipient(uint256 index);
+}
+
+/// Error types for batch checks
+#[derive(SolidityError, Debug)]
+pub enum Error {
+    LengthMismatch(LengthMismatch),
+    EmptyBatch(EmptyBatch),
+    BatchTooLarge(BatchTooLarge),
+    ZeroRecipient(ZeroRecipient),
+    SelfRecipient(SelfRecipient),
+}
+
+/// Revert unless `recipients` and `amounts` describe a non-empty batch of matching length
+/// no larger than `max_size`, paying only valid recipients
+pub fn validate(
+    vm: &dyn Host,
+    recipients: &[Address],
+    amounts: &[U256],
+    max_size: U256,
+) -> Result<(), Error> {
+    if recipients.len() != amounts.len() {
+        return Err(Error::LengthMismatch(LengthMismatch {
+            recipientsLength: U256::from(recipients.len()),
+            amountsLength: U256::from(amounts.len()),
+        }));
+    }
+    if recipients.is_empty() {
+        return Err(Error::EmptyBatch(EmptyBatch {}));
+    }
+    check_size(recipients.len(), max_size)?;
+    for (index, recipient) in recipients.iter().enumerate() {
+        validate_recipient(vm, index, *recipient)?;
+    }
+    Ok(())
+}
+
+/// Revert if a batch of `size` recipients exceeds `max_size`
+pub fn check_size(size: usize, max_size: U256) -> Result<(), Error> {
+    if U256::from(size) > max_size {
+        return Err(Error::BatchTooLarge(BatchTooLarge {
+            size: U256::from(size),
+            maxSize: max_size,
+        }));
+    }
+    Ok(())
+}
+
+/// Revert if `recipient`, found at `index` of its batch, would burn or strand the funds
+pub fn validate_recipient(vm: &dyn Host, index: usize, recipient: Address) -> Result<(), Error> {
+    if recipient.is_zero() {
+        return Err(Error::ZeroRecipient(ZeroRecipient {
+            index: U256::from(index),
+        }));
+    }
+    if recipient == vm.contract_address() {
+        return Err(Error::SelfRecipient(SelfRecipient {
+            index: U256::from(index),
+        }));
+    }
+    Ok(())
+}
+
+/// Sum of a list of amounts, saturating at `U256::MAX` so an overflowing
+/// batch can never pass a `msg.value` check
+pub fn sum_amounts(amounts: &[U256]) -> U256 {
+    amounts
+        .iter()
+        .fold(U256::ZERO, |total, amount| total.saturating_add(*amount))
+}
+
+#[cfg(test)]
+mod test {
+    use super::Error;
+    use super::*;
+    use stylus_sdk::testing::*;
+
+    #[test]
+    fn test_validate() {
+        let vm = TestVM::default();
+        let alice = Address::from([2u8; 20]);
+        let max_size = U256::from(2);
+        let one = [U256::from(1)];
+
+        validate(&vm, &[alice], &one, max_size).unwrap();
+        let err = validate(&vm, &[alice, alice], &one, max_size).unwrap_err();
+        assert!(matches!(err, Error::LengthMismatch(_)));
+        let err = validate(&vm, &[], &[], max_size).unwrap_err();
+        assert!(matches!(err, Error::EmptyBatch(_)));
+        let err = validate(&vm, &[alice; 3], &[U256::from(1); 3], max_size).unwrap_err();
+        assert!(matches!(err, Error::BatchTooLarge(_)));
+
+        // The error names the first offending entry
+        let err =
+            validate(&vm, &[alice, Address::ZERO], &[U256::from(1); 2], max_size).unwrap_err();
+        assert!(matches!(err, Error::ZeroRecipient(e) if e.index == U256::from(1)));
+        let err = validate(&vm, &[vm.contract_address()], &one, max_size).unwrap_err();
+        assert!(matches!(err, Error::SelfRecipient(e) if e.index == U256::ZERO));
+
+        assert_eq!(sum_amounts(&[U256::MAX, U256::from(1)]), U256::MAX);
+    }
+}
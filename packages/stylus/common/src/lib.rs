@@ -0,0 +1,15 @@
+//!
+//! Shared building blocks in Stylus Rust
+//!
+//! Storage components and helpers used both by YourContract and by the
+//! contracts split out of it to keep each one under the Stylus size limit
+//!
+
+#![cfg_attr(not(test), no_std)]
+
+#[macro_use]
+extern crate alloc;
+
+pub mod batch;
+pub mod reentrancy_guard;
+pub mod transfers;
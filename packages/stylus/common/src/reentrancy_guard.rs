@@ -0,0 +1,92 @@
+//!
+//! ReentrancyGuard in Stylus Rust
+//!
+//! A storage component that stops a contract from being re-entered while one
+//! of its guarded functions is still running
+//!
+//! Stylus programs reject reentrant calls unless they are built with the
+//! `reentrant` feature, so this guard keeps value-moving entrypoints safe
+//! even if that feature is turned on later
+//!
+
+use alloc::vec::Vec;
+
+use stylus_sdk::{alloy_sol_types::sol, prelude::*, storage::StorageBool};
+
+sol! {
+    /// A guarded function was called while another guarded call was in progress.
+    #[derive(Debug)]
+    error ReentrancyGuardReentrantCall();
+}
+
+/// Error types for the reentrancy guard
+#[derive(SolidityError, Debug)]
+pub enum Error {
+    ReentrantCall(ReentrancyGuardReentrantCall),
+}
+
+/// State of a reentrancy guard
+#[storage]
+pub struct ReentrancyGuard {
+    entered: StorageBool,
+}
+
+impl ReentrancyGuard {
+    /// Mark a guarded call as in progress
+    /// Fails if another guarded call has not exited yet
+    pub fn enter(&mut self) -> Result<(), Error> {
+        if self.entered.get() {
+            return Err(Error::ReentrantCall(ReentrancyGuardReentrantCall {}));
+        }
+        self.entered.set(true);
+        Ok(())
+    }
+
+    /// Mark the guarded call as finished
+    pub fn exit(&mut self) {
+        self.entered.set(false);
+    }
+
+    /// Whether a guarded call is currently in progress
+    pub fn entered(&self) -> bool {
+        self.entered.get()
+    }
+}
+
+/// Implemented by contracts holding a `ReentrancyGuard` to run calls under it
+pub trait NonReentrant: Sized {
+    /// The guard of the contract
+    fn reentrancy_guard(&mut self) -> &mut ReentrancyGuard;
+
+    /// Run `f` with the reentrancy guard held, releasing it whatever the outcome
+    fn non_reentrant<T, E: From<Error>>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, E>,
+    ) -> Result<T, E> {
+        self.reentrancy_guard().enter()?;
+        let result = f(self);
+        self.reentrancy_guard().exit();
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Error;
+    use super::*;
+    use stylus_sdk::testing::*;
+
+    #[test]
+    fn test_reentrancy_guard() {
+        let vm = TestVM::default();
+        let mut guard = ReentrancyGuard::from(&vm);
+
+        guard.enter().unwrap();
+        assert!(guard.entered());
+        assert!(matches!(guard.enter(), Err(Error::ReentrantCall(_))));
+
+        guard.exit();
+        assert!(!guard.entered());
+        guard.enter().unwrap();
+    }
+}
@@ -0,0 +1,248 @@
+//!
+//! Token transfers in Stylus Rust
+//!
+//! Helpers to take payment in `msg.value` or ERC-20 tokens and to pay it out
+//! again, the zero address stands for native ETH throughout
+//!
+//! ERC-20 calls are checked SafeERC20 style, so tokens returning `true` or
+//! nothing at all are accepted and anything else reverts
+//!
+
+use stylus_sdk::{
+    alloy_primitives::{Address, U256},
+    alloy_sol_types::{sol, SolCall, SolValue},
+    prelude::*,
+    stylus_core::calls::context::Call,
+};
+
+// ERC20 interface used for token transfers
+sol! {
+    interface IERC20 {
+        function transfer(address to, uint256 amount) external returns (bool);
+        function transferFrom(address from, address to, uint256 amount) external returns (bool);
+        function approve(address spender, uint256 amount) external returns (bool);
+        function allowance(address owner, address spender) external view returns (uint256);
+        function permit(address owner, address spender, uint256 value, uint256 deadline, uint8 v, bytes32 r, bytes32 s) external;
+    }
+}
+
+sol! {
+    #[derive(Debug)]
+    error InsufficientValue(uint256 required, uint256 provided);
+    #[derive(Debug)]
+    error RefundFailed(address recipient, uint256 amount);
+    #[derive(Debug)]
+    error PayoutFailed(address token, address recipient, uint256 amount);
+    #[derive(Debug)]
+    error Erc20CallFailed(address token, address to, uint256 amount);
+    #[derive(Debug)]
+    error Erc20TransferReturnedFalse(address token, address to, uint256 amount);
+    #[derive(Debug)]
+    error Erc20InvalidReturnData(address token, address to, uint256 amount);
+}
+
+/// Error types for token transfers
+#[derive(SolidityError, Debug)]
+pub enum Error {
+    InsufficientValue(InsufficientValue),
+    RefundFailed(RefundFailed),
+    PayoutFailed(PayoutFailed),
+    Erc20CallFailed(Erc20CallFailed),
+    Erc20TransferReturnedFalse(Erc20TransferReturnedFalse),
+    Erc20InvalidReturnData(Erc20InvalidReturnData),
+}
+
+/// Take `total` of `token` from `sender` into the contract
+/// Native deposits come out of `msg.value`, ERC-20 deposits are pulled from `sender`
+/// Whatever part of `msg.value` was not spent, all of it for ERC-20 deposits, goes back
+pub fn deposit(vm: &dyn Host, token: Address, sender: Address, total: U256) -> Result<(), Error> {
+    let spent = if token.is_zero() {
+        require_msg_value(vm, total)?;
+        total
+    } else {
+        transfer_erc20_from(vm, token, sender, vm.contract_address(), total)?;
+        U256::ZERO
+    };
+    refund_unspent_value(vm, spent)
+}
+
+/// Send `amount` of `token` held by the contract to `recipient`
+pub fn pay_out(
+    vm: &dyn Host,
+    token: Address,
+    recipient: Address,
+    amount: U256,
+) -> Result<(), Error> {
+    if token.is_zero() {
+        return vm.transfer_eth(recipient, amount).map_err(|_| {
+            Error::PayoutFailed(PayoutFailed {
+                token,
+                recipient,
+                amount,
+            })
+        });
+    }
+
+    let call_data = IERC20::transferCall {
+        to: recipient,
+        amount,
+    }
+    .abi_encode();
+    call_erc20(vm, token, recipient, amount, &call_data)
+}
+
+/// Move `amount` of `token` from `from` to `to` through `transferFrom`, SafeERC20 style
+/// Reverts if the call fails or returns `false`, empty return data counts as success
+pub fn transfer_erc20_from(
+    vm: &dyn Host,
+    token: Address,
+    from: Address,
+    to: Address,
+    amount: U256,
+) -> Result<(), Error> {
+    let call_data = IERC20::transferFromCall { from, to, amount }.abi_encode();
+    call_erc20(vm, token, to, amount, &call_data)
+}
+
+/// Let `spender` pull `amount` of `token` from the contract, SafeERC20 style
+/// Reverts if the call fails or returns `false`, empty return data counts as success
+pub fn approve_erc20(
+    vm: &dyn Host,
+    token: Address,
+    spender: Address,
+    amount: U256,
+) -> Result<(), Error> {
+    let call_data = IERC20::approveCall { spender, amount }.abi_encode();
+    call_erc20(vm, token, spender, amount, &call_data)
+}
+
+/// Read `owner`'s allowance for `spender` on `token`, zero if it cannot be read
+pub fn erc20_allowance(vm: &dyn Host, token: Address, owner: Address, spender: Address) -> U256 {
+    let call_data = IERC20::allowanceCall { owner, spender }.abi_encode();
+    vm.static_call(&Call::new(), token, &call_data)
+        .ok()
+        .and_then(|data| IERC20::allowanceCall::abi_decode_returns(&data, true).ok())
+        .map_or(U256::ZERO, |allowance| allowance._0)
+}
+
+/// Revert unless `msg.value` covers `required`
+pub fn require_msg_value(vm: &dyn Host, required: U256) -> Result<(), Error> {
+    let provided = vm.msg_value();
+    if provided < required {
+        return Err(Error::InsufficientValue(InsufficientValue {
+            required,
+            provided,
+        }));
+    }
+    Ok(())
+}
+
+/// Send the part of `msg.value` that was not `spent` back to the sender
+pub fn refund_unspent_value(vm: &dyn Host, spent: U256) -> Result<(), Error> {
+    let surplus = vm.msg_value().saturating_sub(spent);
+    if surplus > U256::ZERO {
+        let sender = vm.msg_sender();
+        if vm.transfer_eth(sender, surplus).is_err() {
+            return Err(Error::RefundFailed(RefundFailed {
+                recipient: sender,
+                amount: surplus,
+            }));
+        }
+    }
+    Ok(())
+}
+
+/// Perform an ERC20 `transfer` / `transferFrom` / `approve` of `amount` to `to` described by `call_data`
+/// Accepts tokens that return `true` or nothing, anything else reverts
+fn call_erc20(
+    vm: &dyn Host,
+    token: Address,
+    to: Address,
+    amount: U256,
+    call_data: &[u8],
+) -> Result<(), Error> {
+    // A call to an address without code always succeeds with empty return data
+    if vm.code_size(token) == 0 {
+        return Err(Error::Erc20CallFailed(Erc20CallFailed {
+            token,
+            to,
+            amount,
+        }));
+    }
+
+    let return_data = vm
+        .call(&Call::new(), token, call_data)
+        .map_err(|_| Error::Erc20CallFailed(Erc20CallFailed { token, to, amount }))?;
+
+    // Tokens such as USDT return nothing at all
+    if return_data.is_empty() {
+        return Ok(());
+    }
+    // Like SafeERC20, only the first word is read and any trailing bytes are ignored
+    let first_word = return_data.get(..32).unwrap_or_default();
+    match bool::abi_decode(first_word, true) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(Error::Erc20TransferReturnedFalse(
+            Erc20TransferReturnedFalse { token, to, amount },
+        )),
+        Err(_) => Err(Error::Erc20InvalidReturnData(Erc20InvalidReturnData {
+            token,
+            to,
+            amount,
+        })),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Error;
+    use super::*;
+    use stylus_sdk::testing::*;
+
+    /// Simulate a call carrying `value` wei, crediting it to the contract like the EVM would
+    fn fund_call(vm: &TestVM, value: U256) {
+        vm.set_value(value);
+        vm.set_balance(
+            vm.contract_address(),
+            vm.balance(vm.contract_address()) + value,
+        );
+    }
+
+    #[test]
+    fn test_deposit() {
+        let vm = TestVM::default();
+        let sender = vm.msg_sender();
+        let token = Address::from([9u8; 20]);
+        vm.set_code(token, vec![0x00]);
+
+        // Native deposits must be covered by msg.value, the surplus goes back
+        fund_call(&vm, U256::from(10));
+        let err = deposit(&vm, Address::ZERO, sender, U256::from(11)).unwrap_err();
+        assert!(matches!(err, Error::InsufficientValue(_)));
+        deposit(&vm, Address::ZERO, sender, U256::from(7)).unwrap();
+        assert_eq!(vm.balance(sender), U256::from(3));
+        assert_eq!(vm.balance(vm.contract_address()), U256::from(7));
+
+        // ERC-20 deposits are pulled from the sender and send back all of msg.value
+        fund_call(&vm, U256::from(5));
+        deposit(&vm, token, sender, U256::from(20)).unwrap();
+        assert_eq!(vm.balance(sender), U256::from(8));
+        vm.set_value(U256::ZERO);
+
+        let call_data = IERC20::transferFromCall {
+            from: sender,
+            to: vm.contract_address(),
+            amount: U256::from(20),
+        }
+        .abi_encode();
+        vm.mock_call(token, call_data, Ok(false.abi_encode()));
+        let err = deposit(&vm, token, sender, U256::from(20)).unwrap_err();
+        assert!(matches!(err, Error::Erc20TransferReturnedFalse(_)));
+
+        // Paying out to an account refusing ETH reverts
+        let unpayable = Address::from([3u8; 20]);
+        vm.set_balance(unpayable, U256::MAX);
+        let err = pay_out(&vm, Address::ZERO, unpayable, U256::from(1)).unwrap_err();
+        assert!(matches!(err, Error::PayoutFailed(_)));
+    }
+}
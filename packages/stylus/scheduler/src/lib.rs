@@ -0,0 +1,708 @@
+//!
+//! Batch Scheduler in Stylus Rust
+//!
+//! A smart contract escrowing a batch payout now and sending it later, e.g.
+//! payroll on the 1st: once a batch is due anyone can execute it, which pays it
+//! out through YourContract's `sendNativeBatch` / `sendErc20Batch`
+//!
+//! Split out of YourContract to keep both under the Stylus size limit. Payouts
+//! go through the regular batch entrypoints, so they are checked, charged and
+//! recorded like any other batch, with this contract as the sender
+//!
+
+// Allow `cargo stylus export-abi` to generate a main function.
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::{
+    alloy_primitives::{Address, U256},
+    alloy_sol_types::{sol, SolCall},
+    prelude::*,
+    stylus_core::{calls::context::Call, log},
+};
+
+/// Import the building blocks shared with YourContract
+use common::{
+    batch::{
+        self, sum_amounts, BatchTooLarge, EmptyBatch, LengthMismatch, SelfRecipient, ZeroRecipient,
+    },
+    reentrancy_guard::{self, NonReentrant, ReentrancyGuard},
+    transfers::{
+        self, Erc20CallFailed, Erc20InvalidReturnData, Erc20TransferReturnedFalse,
+        InsufficientValue, PayoutFailed, RefundFailed,
+    },
+};
+
+/// How long a due batch is left to `execute_batch` before its creator can cancel it
+/// YourContract can refuse a batch for good, e.g. when the token blacklists a recipient,
+/// the batch outgrows a lowered `maxBatchSize` or the fee rises above the reserve,
+/// and cancelling is then the only way to get the escrow back
+pub const SCHEDULED_BATCH_GRACE_PERIOD: u64 = 7 * 24 * 60 * 60;
+
+// YourContract entrypoints used to price and pay out a batch
+sol! {
+    interface IYourContract {
+        function sendNativeBatch(address[] recipients, uint256[] amounts) external payable;
+        function sendErc20Batch(address token, address[] recipients, uint256[] amounts) external;
+        function quoteFeeFor(address sender, address token, uint256 total) external view returns (uint256);
+        function maxBatchSize() external view returns (uint256);
+    }
+}
+
+// Define errors
+sol! {
+    #[derive(Debug)]
+    error InvalidExecutionTime(uint256 executeAfter);
+    #[derive(Debug)]
+    error ScheduledBatchNotFound(uint256 batchId);
+    #[derive(Debug)]
+    error ScheduledBatchNotDue(uint256 batchId, uint256 executeAfter);
+    #[derive(Debug)]
+    error ScheduledBatchAlreadyDue(uint256 batchId, uint256 executeAfter);
+    #[derive(Debug)]
+    error ScheduledBatchClosed(uint256 batchId);
+    #[derive(Debug)]
+    error NotBatchCreator(uint256 batchId, address account);
+    #[derive(Debug)]
+    error FeeExceedsReserve(uint256 batchId, uint256 fee, uint256 feeReserve);
+    #[derive(Debug)]
+    error YourContractCallFailed();
+    #[derive(Debug)]
+    error BatchExecutionFailed(uint256 batchId);
+}
+
+/// Error types for the contract
+#[derive(SolidityError, Debug)]
+pub enum Error {
+    InsufficientValue(InsufficientValue),
+    RefundFailed(RefundFailed),
+    PayoutFailed(PayoutFailed),
+    Erc20CallFailed(Erc20CallFailed),
+    Erc20TransferReturnedFalse(Erc20TransferReturnedFalse),
+    Erc20InvalidReturnData(Erc20InvalidReturnData),
+    LengthMismatch(LengthMismatch),
+    EmptyBatch(EmptyBatch),
+    BatchTooLarge(BatchTooLarge),
+    ZeroRecipient(ZeroRecipient),
+    SelfRecipient(SelfRecipient),
+    InvalidExecutionTime(InvalidExecutionTime),
+    ScheduledBatchNotFound(ScheduledBatchNotFound),
+    ScheduledBatchNotDue(ScheduledBatchNotDue),
+    ScheduledBatchAlreadyDue(ScheduledBatchAlreadyDue),
+    ScheduledBatchClosed(ScheduledBatchClosed),
+    NotBatchCreator(NotBatchCreator),
+    FeeExceedsReserve(FeeExceedsReserve),
+    YourContractCallFailed(YourContractCallFailed),
+    BatchExecutionFailed(BatchExecutionFailed),
+    ReentrantCall(reentrancy_guard::ReentrancyGuardReentrantCall),
+}
+
+impl From<transfers::Error> for Error {
+    fn from(value: transfers::Error) -> Self {
+        match value {
+            transfers::Error::InsufficientValue(e) => Error::InsufficientValue(e),
+            transfers::Error::RefundFailed(e) => Error::RefundFailed(e),
+            transfers::Error::PayoutFailed(e) => Error::PayoutFailed(e),
+            transfers::Error::Erc20CallFailed(e) => Error::Erc20CallFailed(e),
+            transfers::Error::Erc20TransferReturnedFalse(e) => Error::Erc20TransferReturnedFalse(e),
+            transfers::Error::Erc20InvalidReturnData(e) => Error::Erc20InvalidReturnData(e),
+        }
+    }
+}
+
+impl From<batch::Error> for Error {
+    fn from(value: batch::Error) -> Self {
+        match value {
+            batch::Error::LengthMismatch(e) => Error::LengthMismatch(e),
+            batch::Error::EmptyBatch(e) => Error::EmptyBatch(e),
+            batch::Error::BatchTooLarge(e) => Error::BatchTooLarge(e),
+            batch::Error::ZeroRecipient(e) => Error::ZeroRecipient(e),
+            batch::Error::SelfRecipient(e) => Error::SelfRecipient(e),
+        }
+    }
+}
+
+impl From<reentrancy_guard::Error> for Error {
+    fn from(value: reentrancy_guard::Error) -> Self {
+        match value {
+            reentrancy_guard::Error::ReentrantCall(e) => Error::ReentrantCall(e),
+        }
+    }
+}
+
+// Define events
+sol! {
+    event BatchScheduled(uint256 indexed batchId, address indexed token, address indexed creator, uint256 totalAmount, uint256 recipientCount, uint256 executeAfter);
+    event BatchExecuted(uint256 indexed batchId, address indexed creator, uint256 fee);
+    event BatchCancelled(uint256 indexed batchId, address indexed creator, uint256 totalAmount);
+}
+
+// Define persistent storage using the Solidity ABI.
+// `BatchScheduler` will be the entrypoint.
+sol_storage! {
+    #[entrypoint]
+    pub struct BatchScheduler {
+        ReentrancyGuard reentrancy_guard;
+        address your_contract;
+        uint256 scheduled_batch_count;
+        mapping(uint256 => ScheduledBatch) scheduled_batches;
+    }
+
+    // Batch paid out of escrow once `execute_after` is reached, see `schedule_batch`
+    pub struct ScheduledBatch {
+        address token;
+        address creator;
+        uint256 total_amount;
+        uint256 fee_reserve;
+        uint256 execute_after;
+        bool executed;
+        bool cancelled;
+        address[] recipients;
+        uint256[] amounts;
+    }
+}
+
+/// Declare that `BatchScheduler` is a contract with the following external methods.
+#[public]
+impl BatchScheduler {
+    /// Pay batches out through the YourContract deployed at `your_contract`
+    #[constructor]
+    pub fn constructor(&mut self, your_contract: Address) {
+        self.your_contract.set(your_contract);
+    }
+
+    /// Get the YourContract batches are paid out through
+    pub fn your_contract(&self) -> Address {
+        self.your_contract.get()
+    }
+
+    /// Escrow a batch of `token` now and pay it out with `execute_batch` once
+    /// `execute_after` is reached, the zero address stands for native ETH
+    /// Returns the id of the scheduled batch
+    /// YourContract's fee for the batch is quoted now and held as a reserve on top of
+    /// the total, the part of it not charged at execution goes back to the creator
+    /// Native batches are funded with `msg.value`, any surplus is refunded to the sender,
+    /// as is any `msg.value` sent along with an ERC-20 batch
+    /// Note: User must approve this contract to spend ERC-20 tokens before calling
+    #[payable]
+    pub fn schedule_batch(
+        &mut self,
+        token: Address,
+        recipients: Vec<Address>,
+        amounts: Vec<U256>,
+        execute_after: U256,
+    ) -> Result<U256, Error> {
+        self.non_reentrant(|this| {
+            this.new_scheduled_batch(token, recipients, amounts, execute_after)
+        })
+    }
+
+    /// Pay out scheduled batch `batch_id` through YourContract, callable by anyone once it is due
+    /// YourContract emits the usual `Batch*Sent` event with this contract as the sender
+    /// Reverts with `FeeExceedsReserve` if the fee rose above what was reserved and with
+    /// `BatchExecutionFailed` if YourContract refuses the batch
+    pub fn execute_batch(&mut self, batch_id: U256) -> Result<(), Error> {
+        self.non_reentrant(|this| this.execute_scheduled_batch(batch_id))
+    }
+
+    /// Cancel scheduled batch `batch_id` and return its funds and fee reserve to the creator,
+    /// either before it is due or once it went unexecuted for `SCHEDULED_BATCH_GRACE_PERIOD`
+    pub fn cancel_batch(&mut self, batch_id: U256) -> Result<(), Error> {
+        self.non_reentrant(|this| {
+            let (token, creator, total_amount, fee_reserve, execute_after) =
+                this.open_scheduled_batch(batch_id)?;
+            let caller = this.vm().msg_sender();
+            if caller != creator {
+                return Err(Error::NotBatchCreator(NotBatchCreator {
+                    batchId: batch_id,
+                    account: caller,
+                }));
+            }
+            let now = U256::from(this.vm().block_timestamp());
+            let grace_end = execute_after.saturating_add(U256::from(SCHEDULED_BATCH_GRACE_PERIOD));
+            if now >= execute_after && now < grace_end {
+                return Err(Error::ScheduledBatchAlreadyDue(ScheduledBatchAlreadyDue {
+                    batchId: batch_id,
+                    executeAfter: execute_after,
+                }));
+            }
+
+            this.scheduled_batches.setter(batch_id).cancelled.set(true);
+            transfers::pay_out(this.vm(), token, creator, total_amount + fee_reserve)?;
+
+            log(
+                this.vm(),
+                BatchCancelled {
+                    batchId: batch_id,
+                    creator,
+                    totalAmount: total_amount,
+                },
+            );
+
+            Ok(())
+        })
+    }
+
+    /// Get scheduled batch `batch_id` as
+    /// `(token, creator, total_amount, fee_reserve, execute_after, executed, cancelled)`
+    pub fn scheduled_batch(
+        &self,
+        batch_id: U256,
+    ) -> (Address, Address, U256, U256, U256, bool, bool) {
+        let batch = self.scheduled_batches.getter(batch_id);
+        (
+            batch.token.get(),
+            batch.creator.get(),
+            batch.total_amount.get(),
+            batch.fee_reserve.get(),
+            batch.execute_after.get(),
+            batch.executed.get(),
+            batch.cancelled.get(),
+        )
+    }
+
+    /// Get the `(recipients, amounts)` of scheduled batch `batch_id`
+    pub fn scheduled_batch_transfers(&self, batch_id: U256) -> (Vec<Address>, Vec<U256>) {
+        let batch = self.scheduled_batches.getter(batch_id);
+        (0..batch.recipients.len())
+            .filter_map(|i| Some((batch.recipients.get(i)?, batch.amounts.get(i)?)))
+            .unzip()
+    }
+
+    /// Get the number of batches scheduled so far, ids run from 1 to this value
+    pub fn scheduled_batch_count(&self) -> U256 {
+        self.scheduled_batch_count.get()
+    }
+}
+
+/// Internal helpers, not exposed in the ABI
+impl BatchScheduler {
+    /// Escrow and store a batch for `schedule_batch`
+    fn new_scheduled_batch(
+        &mut self,
+        token: Address,
+        recipients: Vec<Address>,
+        amounts: Vec<U256>,
+        execute_after: U256,
+    ) -> Result<U256, Error> {
+        let max_batch_size = self.your_contract_view(IYourContract::maxBatchSizeCall {})?;
+        batch::validate(self.vm(), &recipients, &amounts, max_batch_size._0)?;
+        // YourContract refuses to pay itself, so such a batch could never run
+        let your_contract = self.your_contract.get();
+        if let Some(index) = recipients.iter().position(|r| *r == your_contract) {
+            return Err(Error::SelfRecipient(SelfRecipient {
+                index: U256::from(index),
+            }));
+        }
+        if execute_after <= U256::from(self.vm().block_timestamp()) {
+            return Err(Error::InvalidExecutionTime(InvalidExecutionTime {
+                executeAfter: execute_after,
+            }));
+        }
+
+        let sender = self.vm().msg_sender();
+        let total = sum_amounts(&amounts);
+        let fee_reserve = self.quote_fee(token, total)?;
+        transfers::deposit(self.vm(), token, sender, total.saturating_add(fee_reserve))?;
+
+        let batch_id = self.scheduled_batch_count.get() + U256::from(1);
+        self.scheduled_batch_count.set(batch_id);
+        let mut batch = self.scheduled_batches.setter(batch_id);
+        batch.token.set(token);
+        batch.creator.set(sender);
+        batch.total_amount.set(total);
+        batch.fee_reserve.set(fee_reserve);
+        batch.execute_after.set(execute_after);
+        for (recipient, amount) in recipients.iter().zip(&amounts) {
+            batch.recipients.push(*recipient);
+            batch.amounts.push(*amount);
+        }
+
+        log(
+            self.vm(),
+            BatchScheduled {
+                batchId: batch_id,
+                token,
+                creator: sender,
+                totalAmount: total,
+                recipientCount: U256::from(recipients.len()),
+                executeAfter: execute_after,
+            },
+        );
+
+        Ok(batch_id)
+    }
+
+    /// Pay out a due scheduled batch for `execute_batch`
+    fn execute_scheduled_batch(&mut self, batch_id: U256) -> Result<(), Error> {
+        let (token, creator, total_amount, fee_reserve, execute_after) =
+            self.open_scheduled_batch(batch_id)?;
+        if U256::from(self.vm().block_timestamp()) < execute_after {
+            return Err(Error::ScheduledBatchNotDue(ScheduledBatchNotDue {
+                batchId: batch_id,
+                executeAfter: execute_after,
+            }));
+        }
+        let fee = self.quote_fee(token, total_amount)?;
+        if fee > fee_reserve {
+            return Err(Error::FeeExceedsReserve(FeeExceedsReserve {
+                batchId: batch_id,
+                fee,
+                feeReserve: fee_reserve,
+            }));
+        }
+
+        self.scheduled_batches.setter(batch_id).executed.set(true);
+
+        // YourContract takes exactly the total plus its fee, so nothing is refunded
+        // and no allowance is left behind
+        let (recipients, amounts) = self.scheduled_batch_transfers(batch_id);
+        let your_contract = self.your_contract.get();
+        let result = if token.is_zero() {
+            let call_data = IYourContract::sendNativeBatchCall {
+                recipients,
+                amounts,
+            }
+            .abi_encode();
+            self.vm().call(
+                &Call::new().value(total_amount + fee),
+                your_contract,
+                &call_data,
+            )
+        } else {
+            transfers::approve_erc20(self.vm(), token, your_contract, total_amount + fee)?;
+            let call_data = IYourContract::sendErc20BatchCall {
+                token,
+                recipients,
+                amounts,
+            }
+            .abi_encode();
+            self.vm().call(&Call::new(), your_contract, &call_data)
+        };
+        if result.is_err() {
+            return Err(Error::BatchExecutionFailed(BatchExecutionFailed {
+                batchId: batch_id,
+            }));
+        }
+
+        let unused_reserve = fee_reserve - fee;
+        if unused_reserve > U256::ZERO {
+            transfers::pay_out(self.vm(), token, creator, unused_reserve)?;
+        }
+
+        log(
+            self.vm(),
+            BatchExecuted {
+                batchId: batch_id,
+                creator,
+                fee,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Look up a scheduled batch that was neither executed nor cancelled
+    /// Returns `(token, creator, total_amount, fee_reserve, execute_after)`
+    fn open_scheduled_batch(
+        &self,
+        batch_id: U256,
+    ) -> Result<(Address, Address, U256, U256, U256), Error> {
+        let (token, creator, total_amount, fee_reserve, execute_after, executed, cancelled) =
+            self.scheduled_batch(batch_id);
+        if creator.is_zero() {
+            return Err(Error::ScheduledBatchNotFound(ScheduledBatchNotFound {
+                batchId: batch_id,
+            }));
+        }
+        if executed || cancelled {
+            return Err(Error::ScheduledBatchClosed(ScheduledBatchClosed {
+                batchId: batch_id,
+            }));
+        }
+        Ok((token, creator, total_amount, fee_reserve, execute_after))
+    }
+
+    /// Get the fee YourContract charges this contract on top of sending `total` of `token`
+    fn quote_fee(&self, token: Address, total: U256) -> Result<U256, Error> {
+        let quote = self.your_contract_view(IYourContract::quoteFeeForCall {
+            sender: self.vm().contract_address(),
+            token,
+            total,
+        })?;
+        Ok(quote._0)
+    }
+
+    /// Read from YourContract through a static `call`
+    fn your_contract_view<C: SolCall>(&self, call: C) -> Result<C::Return, Error> {
+        self.vm()
+            .static_call(&Call::new(), self.your_contract.get(), &call.abi_encode())
+            .ok()
+            .and_then(|data| C::abi_decode_returns(&data, true).ok())
+            .ok_or(Error::YourContractCallFailed(YourContractCallFailed {}))
+    }
+}
+
+impl NonReentrant for BatchScheduler {
+    fn reentrancy_guard(&mut self) -> &mut ReentrancyGuard {
+        &mut self.reentrancy_guard
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Error;
+    use super::*;
+    use stylus_sdk::alloy_sol_types::SolValue;
+    use stylus_sdk::testing::*;
+
+    const YOUR_CONTRACT: Address = Address::new([7u8; 20]);
+
+    /// Simulate a call carrying `value` wei, crediting it to the contract like the EVM would
+    fn fund_call(vm: &TestVM, value: U256) {
+        vm.set_value(value);
+        vm.set_balance(
+            vm.contract_address(),
+            vm.balance(vm.contract_address()) + value,
+        );
+    }
+
+    /// Make YourContract quote `fee` for sending `total` of `token`
+    fn mock_fee(vm: &TestVM, token: Address, total: U256, fee: U256) {
+        let call_data = IYourContract::quoteFeeForCall {
+            sender: vm.contract_address(),
+            token,
+            total,
+        }
+        .abi_encode();
+        vm.mock_static_call(YOUR_CONTRACT, call_data, Ok(fee.abi_encode()));
+    }
+
+    /// Deploy a scheduler paying out through a YourContract with a batch limit of 2
+    fn deploy(vm: &TestVM) -> BatchScheduler {
+        let mut contract = BatchScheduler::from(vm);
+        contract.constructor(YOUR_CONTRACT);
+        let call_data = IYourContract::maxBatchSizeCall {}.abi_encode();
+        vm.mock_static_call(YOUR_CONTRACT, call_data, Ok(U256::from(2).abi_encode()));
+        contract
+    }
+
+    #[test]
+    fn test_scheduled_batch() {
+        let vm = TestVM::default();
+        let mut contract = deploy(&vm);
+        assert_eq!(contract.your_contract(), YOUR_CONTRACT);
+
+        let alice = Address::from([2u8; 20]);
+        let bob = Address::from([3u8; 20]);
+        let token = Address::from([9u8; 20]);
+        vm.set_code(token, vec![0x00]);
+        let creator = vm.msg_sender();
+        vm.set_block_timestamp(1_000);
+        let execute_after = U256::from(2_000);
+        mock_fee(&vm, Address::ZERO, U256::from(30), U256::from(3));
+        mock_fee(&vm, token, U256::from(50), U256::ZERO);
+
+        fund_call(&vm, U256::from(33));
+        let err = contract
+            .schedule_batch(
+                Address::ZERO,
+                vec![alice, bob],
+                vec![U256::from(10), U256::from(20)],
+                U256::from(1_000),
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidExecutionTime(_)));
+        let err = contract
+            .schedule_batch(
+                Address::ZERO,
+                vec![alice, bob, alice],
+                vec![U256::from(10); 3],
+                execute_after,
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::BatchTooLarge(_)));
+        let err = contract
+            .schedule_batch(
+                Address::ZERO,
+                vec![alice, YOUR_CONTRACT],
+                vec![U256::from(10), U256::from(20)],
+                execute_after,
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::SelfRecipient(e) if e.index == U256::from(1)));
+
+        // The fee is reserved on top of the escrowed total
+        vm.set_value(U256::from(32));
+        let err = contract
+            .schedule_batch(
+                Address::ZERO,
+                vec![alice, bob],
+                vec![U256::from(10), U256::from(20)],
+                execute_after,
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::InsufficientValue(_)));
+        vm.set_value(U256::from(33));
+        let id = contract
+            .schedule_batch(
+                Address::ZERO,
+                vec![alice, bob],
+                vec![U256::from(10), U256::from(20)],
+                execute_after,
+            )
+            .unwrap();
+        assert_eq!(id, U256::from(1));
+        assert_eq!(contract.scheduled_batch_count(), U256::from(1));
+        assert_eq!(
+            contract.scheduled_batch(id),
+            (
+                Address::ZERO,
+                creator,
+                U256::from(30),
+                U256::from(3),
+                execute_after,
+                false,
+                false
+            )
+        );
+        assert_eq!(
+            contract.scheduled_batch_transfers(id),
+            (vec![alice, bob], vec![U256::from(10), U256::from(20)])
+        );
+        vm.set_value(U256::ZERO);
+
+        // Nobody can run it early
+        let err = contract.execute_batch(id).unwrap_err();
+        assert!(matches!(err, Error::ScheduledBatchNotDue(_)));
+
+        // A fee raised above the reserve blocks the batch
+        vm.set_block_timestamp(2_000);
+        mock_fee(&vm, Address::ZERO, U256::from(30), U256::from(4));
+        let err = contract.execute_batch(id).unwrap_err();
+        assert!(matches!(err, Error::FeeExceedsReserve(_)));
+
+        // Once due, anyone can execute it exactly once, the unused reserve goes back
+        mock_fee(&vm, Address::ZERO, U256::from(30), U256::from(1));
+        vm.set_sender(bob);
+        contract.execute_batch(id).unwrap();
+        assert_eq!(vm.balance(creator), U256::from(2));
+        assert!(contract.scheduled_batch(id).5);
+        let err = contract.execute_batch(id).unwrap_err();
+        assert!(matches!(err, Error::ScheduledBatchClosed(_)));
+        vm.set_sender(creator);
+        let err = contract.cancel_batch(id).unwrap_err();
+        assert!(matches!(err, Error::ScheduledBatchClosed(_)));
+
+        // The creator can cancel an ERC-20 batch before it is due
+        // Value sent along by mistake is refunded
+        let creator_balance = vm.balance(creator);
+        fund_call(&vm, U256::from(50));
+        let id = contract
+            .schedule_batch(token, vec![alice], vec![U256::from(50)], U256::from(3_000))
+            .unwrap();
+        assert_eq!(vm.balance(creator), creator_balance + U256::from(50));
+        vm.set_value(U256::ZERO);
+        vm.set_sender(bob);
+        let err = contract.cancel_batch(id).unwrap_err();
+        assert!(matches!(err, Error::NotBatchCreator(_)));
+        vm.set_sender(creator);
+        contract.cancel_batch(id).unwrap();
+        assert!(contract.scheduled_batch(id).6);
+        vm.set_block_timestamp(3_000);
+        let err = contract.execute_batch(id).unwrap_err();
+        assert!(matches!(err, Error::ScheduledBatchClosed(_)));
+
+        // Once due, the batch is left to `execute_batch` for the grace period
+        let id = contract
+            .schedule_batch(token, vec![alice], vec![U256::from(50)], U256::from(4_000))
+            .unwrap();
+        vm.set_block_timestamp(4_000);
+        let err = contract.cancel_batch(id).unwrap_err();
+        assert!(matches!(err, Error::ScheduledBatchAlreadyDue(_)));
+        contract.execute_batch(id).unwrap();
+
+        // A batch YourContract refuses, e.g. a token blacklisting a recipient, is not stuck
+        let id = contract
+            .schedule_batch(token, vec![alice], vec![U256::from(50)], U256::from(5_000))
+            .unwrap();
+        let grace_end = 5_000 + SCHEDULED_BATCH_GRACE_PERIOD;
+        vm.set_block_timestamp(grace_end - 1);
+        let err = contract.cancel_batch(id).unwrap_err();
+        assert!(matches!(err, Error::ScheduledBatchAlreadyDue(_)));
+        vm.set_block_timestamp(grace_end);
+        contract.cancel_batch(id).unwrap();
+
+        // Execution reverts when YourContract refuses the batch
+        // TestVM does not roll storage back, so this batch is checked last
+        let id = contract
+            .schedule_batch(
+                token,
+                vec![alice],
+                vec![U256::from(50)],
+                U256::from(grace_end + 1),
+            )
+            .unwrap();
+        let call_data = IYourContract::sendErc20BatchCall {
+            token,
+            recipients: vec![alice],
+            amounts: vec![U256::from(50)],
+        }
+        .abi_encode();
+        vm.mock_call(YOUR_CONTRACT, call_data, Err(vec![]));
+        vm.set_block_timestamp(grace_end + 1);
+        let err = contract.execute_batch(id).unwrap_err();
+        assert!(matches!(err, Error::BatchExecutionFailed(_)));
+
+        let err = contract.execute_batch(U256::from(9)).unwrap_err();
+        assert!(matches!(err, Error::ScheduledBatchNotFound(_)));
+    }
+
+    #[test]
+    fn test_reentrancy_guard() {
+        let vm = TestVM::default();
+        let mut contract = deploy(&vm);
+        let alice = Address::from([2u8; 20]);
+        vm.set_block_timestamp(1_000);
+        mock_fee(&vm, Address::ZERO, U256::from(10), U256::ZERO);
+
+        // TestVM settles `transfer_eth` and mocked calls without running any recipient
+        // code, so a recipient cannot call back into the contract here. Holding the guard
+        // reproduces the state such a callback observes mid-transfer, and every entrypoint
+        // it could reach must reject it before moving funds
+        contract.reentrancy_guard.enter().unwrap();
+        fund_call(&vm, U256::from(10));
+        let err = contract
+            .schedule_batch(
+                Address::ZERO,
+                vec![alice],
+                vec![U256::from(10)],
+                U256::from(2_000),
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::ReentrantCall(_)));
+        let err = contract.execute_batch(U256::from(1)).unwrap_err();
+        assert!(matches!(err, Error::ReentrantCall(_)));
+        let err = contract.cancel_batch(U256::from(1)).unwrap_err();
+        assert!(matches!(err, Error::ReentrantCall(_)));
+
+        // The guard is released after every call, successful or not
+        contract.reentrancy_guard.exit();
+        let id = contract
+            .schedule_batch(
+                Address::ZERO,
+                vec![alice],
+                vec![U256::from(10)],
+                U256::from(2_000),
+            )
+            .unwrap();
+        assert!(!contract.reentrancy_guard.entered());
+        contract.execute_batch(id).unwrap_err();
+        assert!(!contract.reentrancy_guard.entered());
+    }
+}
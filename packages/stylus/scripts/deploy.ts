@@ -1,5 +1,6 @@
 import deployStylusContract from "./deploy_contract";
 import {
+  getContractDataFromDeployments,
   getDeploymentConfig,
   getRpcUrlFromChain,
   printDeployedAddresses,
@@ -36,6 +37,33 @@ export default async function deployScript(deployOptions: DeployOptions) {
     ...deployOptions,
   });
 
+  // Merkle airdrops, vesting, scheduled batches and subscriptions live in their own
+  // contracts to keep each one under the size limit
+  await deployStylusContract({
+    contract: "airdrop",
+    ...deployOptions,
+  });
+  await deployStylusContract({
+    contract: "vesting",
+    ...deployOptions,
+  });
+  await deployStylusContract({
+    contract: "subscriptions",
+    ...deployOptions,
+  });
+
+  // Scheduled batches are paid out through the YourContract deployed above
+  const yourContract = getContractDataFromDeployments(
+    config.deploymentDir,
+    "your-contract",
+    config.chain.id.toString(),
+  );
+  await deployStylusContract({
+    contract: "scheduler",
+    constructorArgs: [yourContract!.address],
+    ...deployOptions,
+  });
+
   // EXAMPLE: Deploy to Orbit Chains, uncomment to try
   // await deployStylusContract({
   //   contract: "counter",
@@ -2,7 +2,7 @@
 //! YourContract in Stylus Rust
 //!
 //! A smart contract that allows changing a state variable of the contract and tracking the changes
-//! It also allows the owner to withdraw the Ether in the contract
+//! It also allows the treasurer to withdraw the Ether in the contract
 //!
 //! This is the Stylus Rust equivalent of the Solidity YourContract.
 //!
@@ -15,24 +15,166 @@ extern crate alloc;
 
 use alloc::string::String;
 use alloc::vec::Vec;
-use core::ptr;
 
 /// Import items from the SDK. The prelude contains common traits and macros.
 use stylus_sdk::{
-    alloy_primitives::{Address, U256},
-    alloy_sol_types::sol,
+    abi::Bytes,
+    alloy_primitives::{address, Address, B256, U256},
+    alloy_sol_types::{sol, SolCall},
+    keccak_const::Keccak256,
     prelude::*,
-    stylus_core::log,
+    stylus_core::{calls::context::Call, log},
 };
 
-/// Import OpenZeppelin Ownable functionality
-use openzeppelin_stylus::access::ownable::{self, IOwnable, Ownable};
+/// Import OpenZeppelin Ownable functionality, with two-step ownership transfers
+use openzeppelin_stylus::access::{
+    ownable,
+    ownable_two_step::{IOwnable2Step, Ownable2Step},
+};
+
+/// Import OpenZeppelin AccessControl functionality
+use openzeppelin_stylus::access::control::{self, AccessControl, IAccessControl};
+
+/// Import OpenZeppelin Pausable functionality
+use openzeppelin_stylus::utils::pausable::{self, IPausable, Pausable};
+
+/// Import the building blocks shared with the contracts split out of this one
+use common::{
+    batch::{
+        self, sum_amounts, BatchTooLarge, EmptyBatch, LengthMismatch, SelfRecipient, ZeroRecipient,
+        DEFAULT_MAX_BATCH_SIZE,
+    },
+    reentrancy_guard::{self, NonReentrant, ReentrancyGuard},
+    transfers::{
+        self, Erc20CallFailed, Erc20InvalidReturnData, Erc20TransferReturnedFalse,
+        InsufficientValue, PayoutFailed, RefundFailed, IERC20,
+    },
+};
+
+/// Role allowed to `withdraw` the contract balance
+pub const TREASURER_ROLE: [u8; 32] = Keccak256::new().update(b"TREASURER_ROLE").finalize();
+
+/// Role allowed to `pause` and `unpause` the contract
+pub const PAUSER_ROLE: [u8; 32] = Keccak256::new().update(b"PAUSER_ROLE").finalize();
+
+/// Role allowed to change contract settings such as the Permit2 address
+pub const CONFIG_ADMIN_ROLE: [u8; 32] = Keccak256::new().update(b"CONFIG_ADMIN_ROLE").finalize();
+
+/// Highest protocol fee that can be configured, 10%
+pub const MAX_FEE_BPS: u64 = 1_000;
+
+/// Basis points in 100%
+const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Rough gas cost of a batch call before any transfer, events and counters included
+const BATCH_BASE_GAS: u64 = 60_000;
+
+/// Calldata gas of one `(address, uint256)` batch entry, 64 bytes at 16 gas each
+const CALLDATA_GAS_PER_RECIPIENT: u64 = 64 * 16;
+
+/// Worst-case gas of one native transfer inside a batch: a value-bearing CALL (9_000) to a
+/// cold account (2_600) that does not exist yet (25_000), or a failed CALL credited to a
+/// fresh `claimable` slot (22_100) along with its escrow update and event, rounded up
+const NATIVE_TRANSFER_GAS: u64 = 45_000;
+
+/// Worst-case gas of one ERC-20 `transferFrom` inside a batch: a cold call into the token,
+/// the allowance and sender balance updates (5_000 each), a recipient balance going from
+/// zero to non-zero (22_100) and the `Transfer` event, rounded up for proxied tokens
+const ERC20_TRANSFER_GAS: u64 = 50_000;
+
+/// Uniswap's Permit2, deployed at the same address on every major chain
+const CANONICAL_PERMIT2: Address = address!("000000000022D473030F116dDEE9F6B43aC78BA3");
+
+// Permit2 signature transfer interface
+sol! {
+    interface IPermit2 {
+        struct TokenPermissions {
+            address token;
+            uint256 amount;
+        }
+
+        struct PermitBatchTransferFrom {
+            TokenPermissions[] permitted;
+            uint256 nonce;
+            uint256 deadline;
+        }
+
+        struct SignatureTransferDetails {
+            address to;
+            uint256 requestedAmount;
+        }
+
+        function permitBatchTransferFrom(PermitBatchTransferFrom permit, SignatureTransferDetails[] transferDetails, address owner, bytes signature) external;
+    }
+}
+
+// Define errors
+sol! {
+    #[derive(Debug)]
+    error NativeTransferFailed(uint256 index, address recipient, uint256 amount);
+    #[derive(Debug)]
+    error PermitFailed(address token, address owner);
+    #[derive(Debug)]
+    error Permit2NotSet();
+    #[derive(Debug)]
+    error Permit2TransferFailed(address token, address owner);
+    #[derive(Debug)]
+    error TokensLengthMismatch(uint256 tokensLength, uint256 recipientsLength);
+    #[derive(Debug)]
+    error WithdrawFailed(address recipient, uint256 amount);
+    #[derive(Debug)]
+    error FeeTooHigh(uint256 feeBps, uint256 maxFeeBps);
+    #[derive(Debug)]
+    error InvalidFeeRecipient();
+    #[derive(Debug)]
+    error InvalidFeeTiers();
+    #[derive(Debug)]
+    error InvalidMaxBatchSize();
+    #[derive(Debug)]
+    error DuplicateRecipient(uint256 index);
+    #[derive(Debug)]
+    error InvalidWithdrawRecipient(address recipient);
+    #[derive(Debug)]
+    error InsufficientAvailableBalance(uint256 available, uint256 requested);
+    #[derive(Debug)]
+    error NothingToClaim(address account);
+}
 
 /// Error types for the contract
 #[derive(SolidityError, Debug)]
 pub enum Error {
     UnauthorizedAccount(ownable::OwnableUnauthorizedAccount),
     InvalidOwner(ownable::OwnableInvalidOwner),
+    AccessControlUnauthorizedAccount(control::AccessControlUnauthorizedAccount),
+    AccessControlBadConfirmation(control::AccessControlBadConfirmation),
+    NativeTransferFailed(NativeTransferFailed),
+    InsufficientValue(InsufficientValue),
+    RefundFailed(RefundFailed),
+    LengthMismatch(LengthMismatch),
+    EmptyBatch(EmptyBatch),
+    Erc20CallFailed(Erc20CallFailed),
+    Erc20TransferReturnedFalse(Erc20TransferReturnedFalse),
+    Erc20InvalidReturnData(Erc20InvalidReturnData),
+    PermitFailed(PermitFailed),
+    Permit2NotSet(Permit2NotSet),
+    Permit2TransferFailed(Permit2TransferFailed),
+    TokensLengthMismatch(TokensLengthMismatch),
+    WithdrawFailed(WithdrawFailed),
+    FeeTooHigh(FeeTooHigh),
+    InvalidFeeRecipient(InvalidFeeRecipient),
+    InvalidFeeTiers(InvalidFeeTiers),
+    BatchTooLarge(BatchTooLarge),
+    InvalidMaxBatchSize(InvalidMaxBatchSize),
+    DuplicateRecipient(DuplicateRecipient),
+    ZeroRecipient(ZeroRecipient),
+    SelfRecipient(SelfRecipient),
+    InvalidWithdrawRecipient(InvalidWithdrawRecipient),
+    InsufficientAvailableBalance(InsufficientAvailableBalance),
+    PayoutFailed(PayoutFailed),
+    NothingToClaim(NothingToClaim),
+    ReentrantCall(reentrancy_guard::ReentrancyGuardReentrantCall),
+    EnforcedPause(pausable::EnforcedPause),
+    ExpectedPause(pausable::ExpectedPause),
 }
 
 impl From<ownable::Error> for Error {
@@ -44,13 +186,74 @@ impl From<ownable::Error> for Error {
     }
 }
 
+impl From<control::Error> for Error {
+    fn from(value: control::Error) -> Self {
+        match value {
+            control::Error::UnauthorizedAccount(e) => Error::AccessControlUnauthorizedAccount(e),
+            control::Error::BadConfirmation(e) => Error::AccessControlBadConfirmation(e),
+        }
+    }
+}
+
+impl From<pausable::Error> for Error {
+    fn from(value: pausable::Error) -> Self {
+        match value {
+            pausable::Error::EnforcedPause(e) => Error::EnforcedPause(e),
+            pausable::Error::ExpectedPause(e) => Error::ExpectedPause(e),
+        }
+    }
+}
+
+impl From<transfers::Error> for Error {
+    fn from(value: transfers::Error) -> Self {
+        match value {
+            transfers::Error::InsufficientValue(e) => Error::InsufficientValue(e),
+            transfers::Error::RefundFailed(e) => Error::RefundFailed(e),
+            transfers::Error::PayoutFailed(e) => Error::PayoutFailed(e),
+            transfers::Error::Erc20CallFailed(e) => Error::Erc20CallFailed(e),
+            transfers::Error::Erc20TransferReturnedFalse(e) => Error::Erc20TransferReturnedFalse(e),
+            transfers::Error::Erc20InvalidReturnData(e) => Error::Erc20InvalidReturnData(e),
+        }
+    }
+}
+
+impl From<batch::Error> for Error {
+    fn from(value: batch::Error) -> Self {
+        match value {
+            batch::Error::LengthMismatch(e) => Error::LengthMismatch(e),
+            batch::Error::EmptyBatch(e) => Error::EmptyBatch(e),
+            batch::Error::BatchTooLarge(e) => Error::BatchTooLarge(e),
+            batch::Error::ZeroRecipient(e) => Error::ZeroRecipient(e),
+            batch::Error::SelfRecipient(e) => Error::SelfRecipient(e),
+        }
+    }
+}
+
+impl From<reentrancy_guard::Error> for Error {
+    fn from(value: reentrancy_guard::Error) -> Self {
+        match value {
+            reentrancy_guard::Error::ReentrantCall(e) => Error::ReentrantCall(e),
+        }
+    }
+}
+
 // Define events
 sol! {
     event GreetingChange(address indexed greetingSetter, string newGreeting, bool premium, uint256 value);
     event NativeTokenSent(address indexed from, address indexed to, uint256 amount);
     event BatchNativeTokenSent(address indexed from, uint256 totalAmount, uint256 recipientCount);
+    event NativeTokenSendFailed(address indexed from, address indexed to, uint256 index, uint256 amount);
     event ERC20TokenSent(address indexed token, address indexed from, address indexed to, uint256 amount);
     event BatchERC20TokenSent(address indexed token, address indexed from, uint256 totalAmount, uint256 recipientCount);
+    event Permit2Updated(address indexed previousPermit2, address indexed newPermit2);
+    event Withdrawn(address indexed token, address indexed to, uint256 amount);
+    event FeeConfigUpdated(uint256 feeBps, address indexed feeRecipient);
+    event FeeExemptionUpdated(address indexed account, bool exempt);
+    event FeeTiersUpdated(address indexed token, uint256[] minVolumes, uint256[] feeBps);
+    event MaxBatchSizeUpdated(uint256 previousMaxBatchSize, uint256 newMaxBatchSize);
+    event ClaimableCredited(address indexed account, address indexed from, uint256 index, uint256 amount);
+    event Claimed(address indexed account, uint256 amount);
+    event FeeCollected(address indexed token, address indexed from, address indexed feeRecipient, uint256 amount);
 }
 
 // Define persistent storage using the Solidity ABI.
@@ -58,7 +261,9 @@ sol! {
 sol_storage! {
     #[entrypoint]
     pub struct YourContract {
-        Ownable ownable;
+        Ownable2Step ownable;
+        AccessControl access;
+        Pausable pausable;
         string greeting;
         bool premium;
         uint256 total_counter;
@@ -67,22 +272,46 @@ sol_storage! {
         uint256 total_erc20_sent;
         mapping(address => uint256) user_native_sent;
         mapping(address => uint256) user_erc20_sent;
+        mapping(address => uint256) token_total_sent;
+        mapping(address => mapping(address => uint256)) token_user_sent;
+        address permit2;
+        ReentrancyGuard reentrancy_guard;
+        uint256 fee_bps;
+        address fee_recipient;
+        mapping(address => uint256) fees_collected;
+        mapping(address => bool) fee_exempt;
+        mapping(address => uint256[]) fee_tier_min_volumes;
+        mapping(address => uint256[]) fee_tier_bps;
+        uint256 max_batch_size;
+        mapping(address => uint256) escrowed;
+        mapping(address => uint256) claimable;
     }
 }
 
 /// Declare that `YourContract` is a contract with the following external methods.
 #[public]
-#[implements(IOwnable<Error = Error>)]
+#[implements(IOwnable2Step<Error = Error>, IAccessControl<Error = Error>, IPausable)]
 impl YourContract {
     #[constructor]
     pub fn constructor(&mut self, initial_owner: Address) -> Result<(), Error> {
         // Initialize Ownable with the initial owner using OpenZeppelin pattern
         self.ownable.constructor(initial_owner)?;
+        // The initial owner starts with every operational role and can hand them out
+        for role in [
+            AccessControl::DEFAULT_ADMIN_ROLE,
+            TREASURER_ROLE,
+            PAUSER_ROLE,
+            CONFIG_ADMIN_ROLE,
+        ] {
+            self.access._grant_role(role.into(), initial_owner);
+        }
         self.greeting.set_str("Building Unstoppable Apps!!!");
         self.premium.set(false);
         self.total_counter.set(U256::ZERO);
         self.total_native_sent.set(U256::ZERO);
         self.total_erc20_sent.set(U256::ZERO);
+        self.permit2.set(CANONICAL_PERMIT2);
+        self.max_batch_size.set(U256::from(DEFAULT_MAX_BATCH_SIZE));
         Ok(())
     }
 
@@ -108,7 +337,9 @@ impl YourContract {
 
     /// Function that allows anyone to change the state variable "greeting" of the contract and increase the counters
     #[payable]
-    pub fn set_greeting(&mut self, new_greeting: String) {
+    pub fn set_greeting(&mut self, new_greeting: String) -> Result<(), Error> {
+        self.pausable.when_not_paused()?;
+
         // Change state variables
         self.greeting.set_str(&new_greeting);
 
@@ -136,79 +367,146 @@ impl YourContract {
                 value: msg_value,
             },
         );
+
+        Ok(())
     }
 
-    /// Function that allows the owner to withdraw all the Ether in the contract
-    /// The function can only be called by the owner of the contract
+    /// Function that allows the treasurer to withdraw all the Ether in the contract
+    /// The Ether is sent to the owner, the caller needs `TREASURER_ROLE`
+    /// Reverts once ownership was renounced, use `withdraw_to` instead
     pub fn withdraw(&mut self) -> Result<(), Error> {
-        // Check if caller is owner using OpenZeppelin's only_owner
-        self.ownable.only_owner()?;
+        self.access.only_role(TREASURER_ROLE.into())?;
 
-        // Get contract balance and transfer to owner using transfer_eth
-        let balance = self.vm().balance(self.vm().contract_address());
-        if balance > U256::ZERO {
-            let owner = self.ownable.owner();
-            let _ = self.vm().transfer_eth(owner, balance);
+        let owner = self.ownable.owner();
+        if owner.is_zero() {
+            return Err(Error::InvalidOwner(ownable::OwnableInvalidOwner { owner }));
         }
 
-        Ok(())
+        self.non_reentrant(|this| {
+            // Transfer everything not held in escrow to the owner
+            let balance = this.available_native_balance();
+            if balance > U256::ZERO {
+                this.withdraw_native(owner, balance)?;
+            }
+
+            Ok(())
+        })
     }
 
-    /// Send native token (ETH) to a single recipient
-    #[payable]
-    pub fn send_native_individual(&mut self, recipient: Address, amount: U256) {
-        // Transfer native token
-        let _ = self.vm().transfer_eth(recipient, amount);
+    /// Withdraw exactly `amount` wei of the contract balance to `recipient`
+    /// Funds held in escrow cannot be withdrawn, the caller needs `TREASURER_ROLE`
+    pub fn withdraw_to(&mut self, recipient: Address, amount: U256) -> Result<(), Error> {
+        self.access.only_role(TREASURER_ROLE.into())?;
+        self.validate_withdraw_recipient(recipient)?;
 
-        let sender = self.vm().msg_sender();
-        
-        // Update counters
-        let current_total = self.total_native_sent.get();
-        self.total_native_sent.set(current_total + amount);
-        
-        let current_user = self.user_native_sent.get(sender);
-        self.user_native_sent.insert(sender, current_user + amount);
+        self.non_reentrant(|this| {
+            let available = this.available_native_balance();
+            if amount > available {
+                return Err(Error::InsufficientAvailableBalance(
+                    InsufficientAvailableBalance {
+                        available,
+                        requested: amount,
+                    },
+                ));
+            }
+            this.withdraw_native(recipient, amount)
+        })
+    }
 
-        // Emit event
-        log(
-            self.vm(),
-            NativeTokenSent {
-                from: sender,
-                to: recipient,
-                amount,
-            },
-        );
+    /// Withdraw `amount` of an ERC20 `token` held by the contract to `recipient`
+    /// Meant for recovering tokens sent here by mistake, the caller needs `TREASURER_ROLE`
+    pub fn withdraw_erc20(
+        &mut self,
+        token: Address,
+        recipient: Address,
+        amount: U256,
+    ) -> Result<(), Error> {
+        self.access.only_role(TREASURER_ROLE.into())?;
+        self.validate_withdraw_recipient(recipient)?;
+
+        self.non_reentrant(|this| {
+            transfers::pay_out(this.vm(), token, recipient, amount)?;
+
+            log(
+                this.vm(),
+                Withdrawn {
+                    token,
+                    to: recipient,
+                    amount,
+                },
+            );
+
+            Ok(())
+        })
+    }
+
+    /// Send native token (ETH) to a single recipient
+    /// `msg.value` must cover `amount` plus `quote_fee(amount)`, any surplus is refunded to the sender
+    /// If the transfer fails the amount is credited to the recipient's `claimable` balance
+    #[payable]
+    pub fn send_native_individual(
+        &mut self,
+        recipient: Address,
+        amount: U256,
+    ) -> Result<(), Error> {
+        self.pausable.when_not_paused()?;
+        self.non_reentrant(|this| this.native_individual(recipient, amount))
     }
 
     /// Send native token (ETH) to multiple recipients in batch
+    /// `msg.value` must cover the sum of `amounts` plus its fee, any surplus is refunded to the sender
+    /// A failed transfer never reverts the batch, its amount is credited to the
+    /// recipient's `claimable` balance instead
     #[payable]
-    pub fn send_native_batch(&mut self, recipients: Vec<Address>, amounts: Vec<U256>) {
-        let sender = self.vm().msg_sender();
-        let mut total_amount = U256::ZERO;
+    pub fn send_native_batch(
+        &mut self,
+        recipients: Vec<Address>,
+        amounts: Vec<U256>,
+    ) -> Result<(), Error> {
+        self.pausable.when_not_paused()?;
+        self.non_reentrant(|this| this.native_batch(recipients, amounts))
+    }
 
-        // Send to each recipient
-        for (i, recipient) in recipients.iter().enumerate() {
-            let amount = amounts[i];
-            let _ = self.vm().transfer_eth(*recipient, amount);
-            total_amount += amount;
-        }
+    /// Send native token (ETH) to multiple recipients in batch, all or nothing
+    /// Reverts the whole transaction if any single transfer fails
+    /// `msg.value` must cover the sum of `amounts` plus its fee, any surplus is refunded to the sender
+    #[payable]
+    pub fn send_native_batch_atomic(
+        &mut self,
+        recipients: Vec<Address>,
+        amounts: Vec<U256>,
+    ) -> Result<(), Error> {
+        self.pausable.when_not_paused()?;
+        self.non_reentrant(|this| this.native_batch_atomic(recipients, amounts))
+    }
 
-        // Update counters
-        let current_total = self.total_native_sent.get();
-        self.total_native_sent.set(current_total + total_amount);
-        
-        let current_user = self.user_native_sent.get(sender);
-        self.user_native_sent.insert(sender, current_user + total_amount);
+    /// Send native token (ETH) to multiple recipients in batch, best effort
+    /// Keeps going after a failed transfer and returns which transfers landed
+    /// Only successful transfers are counted, each failure emits `NativeTokenSendFailed`
+    /// `msg.value` must cover the sum of `amounts` plus its fee, the fee is only charged on
+    /// transfers that landed and the value of failed transfers is refunded
+    #[payable]
+    pub fn send_native_batch_best_effort(
+        &mut self,
+        recipients: Vec<Address>,
+        amounts: Vec<U256>,
+    ) -> Result<Vec<bool>, Error> {
+        self.pausable.when_not_paused()?;
+        self.non_reentrant(|this| this.native_batch_best_effort(recipients, amounts))
+    }
 
-        // Emit event
-        log(
-            self.vm(),
-            BatchNativeTokenSent {
-                from: sender,
-                totalAmount: total_amount,
-                recipientCount: U256::from(recipients.len()),
-            },
-        );
+    /// Send native token (ETH) to multiple distinct recipients in batch
+    /// Same as `send_native_batch`, failed transfers included, but reverts with
+    /// `DuplicateRecipient` if an address appears more than once
+    #[payable]
+    pub fn send_native_batch_unique(
+        &mut self,
+        recipients: Vec<Address>,
+        amounts: Vec<U256>,
+    ) -> Result<(), Error> {
+        self.pausable.when_not_paused()?;
+        self.require_unique(&recipients)?;
+        self.non_reentrant(|this| this.native_batch(recipients, amounts))
     }
 
     /// Get total native tokens sent through the contract
@@ -221,201 +519,2516 @@ impl YourContract {
         self.user_native_sent.get(user)
     }
 
-    /// Send ERC-20 token to a single recipient
+    /// Send ERC-20 token to a single recipient, the fee is pulled on top of `amount`
     /// Note: User must approve this contract to spend tokens before calling
-    pub fn send_erc20_individual(&mut self, token: Address, recipient: Address, amount: U256) {
+    pub fn send_erc20_individual(
+        &mut self,
+        token: Address,
+        recipient: Address,
+        amount: U256,
+    ) -> Result<(), Error> {
+        self.pausable.when_not_paused()?;
         let sender = self.vm().msg_sender();
+        self.non_reentrant(|this| this.erc20_individual(token, sender, recipient, amount))
+    }
 
-        // Create transferFrom call data: transferFrom(address from, address to, uint256 amount)
-        // Function selector for transferFrom(address,address,uint256) is 0x23b872dd
-        let mut call_data = Vec::with_capacity(100);
-        call_data.extend_from_slice(&[0x23, 0xb8, 0x72, 0xdd]); // transferFrom selector
-        
-        // Encode sender address (32 bytes, left-padded)
-        let mut sender_bytes = [0u8; 32];
-        sender_bytes[12..32].copy_from_slice(sender.as_slice());
-        call_data.extend_from_slice(&sender_bytes);
-        
-        // Encode recipient address (32 bytes, left-padded)
-        let mut recipient_bytes = [0u8; 32];
-        recipient_bytes[12..32].copy_from_slice(recipient.as_slice());
-        call_data.extend_from_slice(&recipient_bytes);
-        
-        // Encode amount (32 bytes, big-endian)
-        let amount_bytes = amount.to_be_bytes::<32>();
-        call_data.extend_from_slice(&amount_bytes);
-
-        // Make the call to the ERC-20 contract using raw call
-        unsafe {
-            let mut return_size = 0usize;
-            let _ = self.vm().call_contract(
-                token.as_ptr(),
-                call_data.as_ptr(),
-                call_data.len(),
-                ptr::null(),
-                0,
-                &mut return_size,
-            );
-        }
+    /// Send ERC-20 token to multiple recipients in batch, the fee is pulled on top of the total
+    /// Note: User must approve this contract to spend tokens before calling
+    pub fn send_erc20_batch(
+        &mut self,
+        token: Address,
+        recipients: Vec<Address>,
+        amounts: Vec<U256>,
+    ) -> Result<(), Error> {
+        self.pausable.when_not_paused()?;
+        let sender = self.vm().msg_sender();
+        self.non_reentrant(|this| this.erc20_batch(token, sender, &recipients, &amounts))
+    }
 
-        // Update counters
-        let current_total = self.total_erc20_sent.get();
-        self.total_erc20_sent.set(current_total + amount);
-        
-        let current_user = self.user_erc20_sent.get(sender);
-        self.user_erc20_sent.insert(sender, current_user + amount);
+    /// Send ERC-20 token to multiple distinct recipients in batch
+    /// Same as `send_erc20_batch` but reverts with `DuplicateRecipient` if an
+    /// address appears more than once
+    /// Note: User must approve this contract to spend tokens before calling
+    pub fn send_erc20_batch_unique(
+        &mut self,
+        token: Address,
+        recipients: Vec<Address>,
+        amounts: Vec<U256>,
+    ) -> Result<(), Error> {
+        self.pausable.when_not_paused()?;
+        self.require_unique(&recipients)?;
+        let sender = self.vm().msg_sender();
+        self.non_reentrant(|this| this.erc20_batch(token, sender, &recipients, &amounts))
+    }
 
-        // Emit event
-        log(
-            self.vm(),
-            ERC20TokenSent {
-                token,
-                from: sender,
-                to: recipient,
-                amount,
-            },
-        );
+    /// Find the indices of every repeated entry in `recipients`, in ascending order
+    /// The first occurrence of an address is not reported, only its repeats
+    pub fn find_duplicates(&self, recipients: Vec<Address>) -> Vec<U256> {
+        duplicate_indices(&recipients)
+            .into_iter()
+            .map(U256::from)
+            .collect()
     }
 
-    /// Send ERC-20 token to multiple recipients in batch
-    /// Note: User must approve this contract to spend tokens before calling
-    pub fn send_erc20_batch(&mut self, token: Address, recipients: Vec<Address>, amounts: Vec<U256>) {
+    /// Send ERC-20 token to a single recipient, approving the transfer with an
+    /// EIP-2612 `permit` signature in the same transaction
+    /// `value` is the allowance signed by the caller and must cover `amount` plus its fee
+    #[allow(clippy::too_many_arguments)]
+    pub fn send_erc20_individual_with_permit(
+        &mut self,
+        token: Address,
+        recipient: Address,
+        amount: U256,
+        value: U256,
+        deadline: U256,
+        v: u8,
+        r: B256,
+        s: B256,
+    ) -> Result<(), Error> {
+        self.pausable.when_not_paused()?;
         let sender = self.vm().msg_sender();
-        let mut total_amount = U256::ZERO;
+        self.non_reentrant(|this| {
+            let required = amount.saturating_add(this.fee_on(sender, token, amount));
+            this.permit(token, sender, value, deadline, v, r, s, required)?;
+            this.erc20_individual(token, sender, recipient, amount)
+        })
+    }
 
-        // Send to each recipient
-        for (i, recipient) in recipients.iter().enumerate() {
-            let amount = amounts[i];
-            
-            // Create transferFrom call data: transferFrom(address from, address to, uint256 amount)
-            let mut call_data = Vec::with_capacity(100);
-            call_data.extend_from_slice(&[0x23, 0xb8, 0x72, 0xdd]); // transferFrom selector
-            
-            // Encode sender address (32 bytes, left-padded)
-            let mut sender_bytes = [0u8; 32];
-            sender_bytes[12..32].copy_from_slice(sender.as_slice());
-            call_data.extend_from_slice(&sender_bytes);
-            
-            // Encode recipient address (32 bytes, left-padded)
-            let mut recipient_bytes = [0u8; 32];
-            recipient_bytes[12..32].copy_from_slice(recipient.as_slice());
-            call_data.extend_from_slice(&recipient_bytes);
-            
-            // Encode amount (32 bytes, big-endian)
-            let amount_bytes = amount.to_be_bytes::<32>();
-            call_data.extend_from_slice(&amount_bytes);
-
-            // Make the call to the ERC-20 contract using raw call
-            unsafe {
-                let mut return_size = 0usize;
-                let _ = self.vm().call_contract(
-                    token.as_ptr(),
-                    call_data.as_ptr(),
-                    call_data.len(),
-                    ptr::null(),
-                    0,
-                    &mut return_size,
-                );
+    /// Send ERC-20 token to multiple recipients in batch, approving the transfers
+    /// with an EIP-2612 `permit` signature in the same transaction
+    /// `value` is the allowance signed by the caller and must cover the sum of `amounts` plus its fee
+    #[allow(clippy::too_many_arguments)]
+    pub fn send_erc20_batch_with_permit(
+        &mut self,
+        token: Address,
+        recipients: Vec<Address>,
+        amounts: Vec<U256>,
+        value: U256,
+        deadline: U256,
+        v: u8,
+        r: B256,
+        s: B256,
+    ) -> Result<(), Error> {
+        self.pausable.when_not_paused()?;
+        let sender = self.vm().msg_sender();
+        self.non_reentrant(|this| {
+            let total = sum_amounts(&amounts);
+            let required = total.saturating_add(this.fee_on(sender, token, total));
+            this.permit(token, sender, value, deadline, v, r, s, required)?;
+            this.erc20_batch(token, sender, &recipients, &amounts)
+        })
+    }
+
+    /// Send ERC-20 token to multiple recipients in batch, pulling the funds through
+    /// Permit2's `permitBatchTransferFrom` with a signature instead of an approval
+    /// The caller signs one `(token, amount)` permission per recipient, in order,
+    /// with this contract as the spender, followed by one for `quote_fee(total)` when it is non-zero
+    pub fn send_erc20_batch_with_permit2(
+        &mut self,
+        token: Address,
+        recipients: Vec<Address>,
+        amounts: Vec<U256>,
+        nonce: U256,
+        deadline: U256,
+        signature: Bytes,
+    ) -> Result<(), Error> {
+        self.pausable.when_not_paused()?;
+        self.non_reentrant(|this| {
+            this.erc20_batch_with_permit2(token, recipients, amounts, nonce, deadline, signature)
+        })
+    }
+
+    /// Send a mix of tokens to multiple recipients in a single call
+    /// `tokens[i]` is sent to `recipients[i]`, the zero address stands for native ETH
+    /// Emits the usual per-item event plus one aggregate batch event per token
+    /// `msg.value` must cover the native amounts plus their fee, any surplus is refunded to the sender
+    /// The fee is charged per token on top of that token's total
+    /// Note: User must approve this contract to spend each ERC-20 token before calling
+    #[payable]
+    pub fn send_multi_token_batch(
+        &mut self,
+        tokens: Vec<Address>,
+        recipients: Vec<Address>,
+        amounts: Vec<U256>,
+    ) -> Result<(), Error> {
+        self.pausable.when_not_paused()?;
+        self.non_reentrant(|this| this.multi_token_batch(tokens, recipients, amounts))
+    }
+
+    /// Pay out the caller's `claimable` balance left by failed native transfers
+    /// The fee recipient collects native fees the same way
+    /// Stays available while the contract is paused, like `withdraw`
+    pub fn claim(&mut self) -> Result<(), Error> {
+        self.non_reentrant(|this| {
+            let account = this.vm().msg_sender();
+            let amount = this.claimable.get(account);
+            if amount.is_zero() {
+                return Err(Error::NothingToClaim(NothingToClaim { account }));
             }
-            
-            total_amount += amount;
-        }
 
-        // Update counters
-        let current_total = self.total_erc20_sent.get();
-        self.total_erc20_sent.set(current_total + total_amount);
-        
-        let current_user = self.user_erc20_sent.get(sender);
-        self.user_erc20_sent.insert(sender, current_user + total_amount);
+            this.claimable.insert(account, U256::ZERO);
+            this.release_escrow(Address::ZERO, amount);
+            transfers::pay_out(this.vm(), Address::ZERO, account, amount)?;
 
-        // Emit event
-        log(
-            self.vm(),
-            BatchERC20TokenSent {
-                token,
-                from: sender,
-                totalAmount: total_amount,
-                recipientCount: U256::from(recipients.len()),
-            },
-        );
+            log(this.vm(), Claimed { account, amount });
+
+            Ok(())
+        })
+    }
+
+    /// Get the native amount `account` can collect with `claim`, native fees included
+    pub fn claimable(&self, account: Address) -> U256 {
+        self.claimable.get(account)
+    }
+
+    /// Get the amount of `token` held by the contract on behalf of users
+    /// The zero address stands for native ETH, escrowed funds cannot be withdrawn
+    pub fn get_escrowed(&self, token: Address) -> U256 {
+        self.escrowed.get(token)
     }
 
     /// Get total ERC-20 tokens sent through the contract
+    /// Note: raw amounts of every token are summed together, prefer `get_total_sent_for_token`
     pub fn get_total_erc20_sent(&self) -> U256 {
         self.total_erc20_sent.get()
     }
 
     /// Get ERC-20 tokens sent by a specific user
+    /// Note: raw amounts of every token are summed together, prefer `get_user_sent_for_token`
     pub fn get_user_erc20_sent(&self, user: Address) -> U256 {
         self.user_erc20_sent.get(user)
     }
 
-    /// Allow contract to receive ETH (equivalent to receive() function)
-    #[payable]
-    pub fn receive_ether(&self) {
-        // This function allows the contract to receive ETH
-        // The #[payable] attribute allows it to accept value
+    /// Get total amount of a specific ERC-20 token sent through the contract
+    pub fn get_total_sent_for_token(&self, token: Address) -> U256 {
+        self.token_total_sent.get(token)
     }
-}
 
-/// Implementation of the IOwnable interface
-#[public]
-impl IOwnable for YourContract {
-    type Error = Error;
+    /// Get amount of a specific ERC-20 token sent by a specific user
+    pub fn get_user_sent_for_token(&self, token: Address, user: Address) -> U256 {
+        self.token_user_sent.getter(token).get(user)
+    }
 
-    fn owner(&self) -> Address {
-        self.ownable.owner()
+    /// Get the Permit2 contract used by `send_erc20_batch_with_permit2`
+    pub fn permit2(&self) -> Address {
+        self.permit2.get()
     }
 
-    fn transfer_ownership(&mut self, new_owner: Address) -> Result<(), Self::Error> {
-        Ok(self.ownable.transfer_ownership(new_owner)?)
+    /// Set the Permit2 contract used by `send_erc20_batch_with_permit2`
+    /// The caller needs `CONFIG_ADMIN_ROLE`
+    pub fn set_permit2(&mut self, permit2: Address) -> Result<(), Error> {
+        self.access.only_role(CONFIG_ADMIN_ROLE.into())?;
+
+        let previous = self.permit2.get();
+        self.permit2.set(permit2);
+
+        log(
+            self.vm(),
+            Permit2Updated {
+                previousPermit2: previous,
+                newPermit2: permit2,
+            },
+        );
+
+        Ok(())
     }
 
-    fn renounce_ownership(&mut self) -> Result<(), Self::Error> {
-        Ok(self.ownable.renounce_ownership()?)
+    /// Get the protocol fee in basis points charged on top of every send
+    pub fn fee_bps(&self) -> U256 {
+        self.fee_bps.get()
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use stylus_sdk::testing::*;
+    /// Get the address receiving the protocol fee
+    pub fn fee_recipient(&self) -> Address {
+        self.fee_recipient.get()
+    }
 
-    #[no_mangle]
-    pub unsafe extern "C" fn emit_log(_pointer: *const u8, _len: usize, _: usize) {}
-    #[no_mangle]
-    pub unsafe extern "C" fn msg_sender(_sender: *mut u8) {}
+    /// Set the protocol fee, capped at `MAX_FEE_BPS`, and the address receiving it
+    /// The caller needs `CONFIG_ADMIN_ROLE`
+    pub fn set_fee(&mut self, fee_bps: U256, fee_recipient: Address) -> Result<(), Error> {
+        self.access.only_role(CONFIG_ADMIN_ROLE.into())?;
 
-    #[test]
-    fn test_your_contract() {
-        let vm = TestVM::default();
-        let mut contract = YourContract::from(&vm);
+        let max_fee_bps = U256::from(MAX_FEE_BPS);
+        if fee_bps > max_fee_bps {
+            return Err(Error::FeeTooHigh(FeeTooHigh {
+                feeBps: fee_bps,
+                maxFeeBps: max_fee_bps,
+            }));
+        }
+        if fee_bps > U256::ZERO && fee_recipient.is_zero() {
+            return Err(Error::InvalidFeeRecipient(InvalidFeeRecipient {}));
+        }
 
-        // Test initialization
-        let owner_addr = Address::from([1u8; 20]);
-        let _ = contract.constructor(owner_addr);
+        self.fee_bps.set(fee_bps);
+        self.fee_recipient.set(fee_recipient);
 
-        assert_eq!(contract.owner(), owner_addr);
-        assert_eq!(contract.greeting(), "Building Unstoppable Apps!!!");
-        assert_eq!(contract.premium(), false);
-        assert_eq!(contract.total_counter(), U256::ZERO);
+        log(
+            self.vm(),
+            FeeConfigUpdated {
+                feeBps: fee_bps,
+                feeRecipient: fee_recipient,
+            },
+        );
 
-        // Test setting greeting without payment
-        contract.set_greeting("Hello World".to_string());
-        assert_eq!(contract.greeting(), "Hello World");
-        assert_eq!(contract.premium(), false);
-        assert_eq!(contract.total_counter(), U256::from(1));
+        Ok(())
+    }
 
-        // Test user greeting counter
-        let sender = vm.msg_sender();
-        assert_eq!(contract.user_greeting_counter(sender), U256::from(1));
+    /// Get the fee charged on top of sending `total` at the base rate
+    /// Exemptions and volume tiers are not applied, see `quote_fee_for`
+    pub fn quote_fee(&self, total: U256) -> U256 {
+        apply_bps(total, self.fee_bps.get())
+    }
 
-        // Test setting greeting with payment
-        vm.set_value(U256::from(100));
-        contract.set_greeting("Premium Hello".to_string());
-        assert_eq!(contract.greeting(), "Premium Hello");
-        assert_eq!(contract.premium(), true);
-        assert_eq!(contract.total_counter(), U256::from(2));
-        assert_eq!(contract.user_greeting_counter(sender), U256::from(2));
+    /// Get the fee `sender` pays on top of sending `total` of `token`
+    /// The zero address stands for native ETH
+    pub fn quote_fee_for(&self, sender: Address, token: Address, total: U256) -> U256 {
+        self.fee_on(sender, token, total)
+    }
+
+    /// Get the fee rate in basis points `sender` currently pays when sending `token`
+    /// Exempt senders pay nothing, otherwise the highest volume tier reached by the
+    /// sender's cumulative volume in `token` applies, falling back to the base rate
+    /// Tiers never raise the rate above the base rate
+    pub fn effective_fee_bps(&self, sender: Address, token: Address) -> U256 {
+        if self.fee_exempt.get(sender) {
+            return U256::ZERO;
+        }
+
+        let volume = if token.is_zero() {
+            self.user_native_sent.get(sender)
+        } else {
+            self.token_user_sent.getter(token).get(sender)
+        };
+        self.tier_fee_bps(token, volume)
+    }
+
+    /// Check whether `account` is exempt from the protocol fee
+    pub fn is_fee_exempt(&self, account: Address) -> bool {
+        self.fee_exempt.get(account)
+    }
+
+    /// Exempt `account` from the protocol fee, or lift its exemption
+    /// The caller needs `CONFIG_ADMIN_ROLE`
+    pub fn set_fee_exempt(&mut self, account: Address, exempt: bool) -> Result<(), Error> {
+        self.access.only_role(CONFIG_ADMIN_ROLE.into())?;
+
+        self.fee_exempt.insert(account, exempt);
+
+        log(self.vm(), FeeExemptionUpdated { account, exempt });
+
+        Ok(())
+    }
+
+    /// Get the volume tiers of `token` as `(min_volumes, fee_bps)`
+    pub fn fee_tiers(&self, token: Address) -> (Vec<U256>, Vec<U256>) {
+        let min_volumes = self.fee_tier_min_volumes.getter(token);
+        let tier_bps = self.fee_tier_bps.getter(token);
+        (0..min_volumes.len())
+            .filter_map(|i| Some((min_volumes.get(i)?, tier_bps.get(i)?)))
+            .unzip()
+    }
+
+    /// Replace the volume tiers of `token`, the zero address stands for native ETH
+    /// A sender whose cumulative volume reaches `min_volumes[i]` pays `fee_bps[i]`, capped
+    /// at the base rate, `min_volumes` must be strictly ascending, `fee_bps` non-increasing
+    /// and every rate at most `MAX_FEE_BPS`
+    /// Passing empty lists removes the tiers, the caller needs `CONFIG_ADMIN_ROLE`
+    pub fn set_fee_tiers(
+        &mut self,
+        token: Address,
+        min_volumes: Vec<U256>,
+        fee_bps: Vec<U256>,
+    ) -> Result<(), Error> {
+        self.access.only_role(CONFIG_ADMIN_ROLE.into())?;
+
+        if min_volumes.len() != fee_bps.len()
+            || min_volumes.windows(2).any(|pair| pair[0] >= pair[1])
+            || fee_bps.windows(2).any(|pair| pair[0] < pair[1])
+        {
+            return Err(Error::InvalidFeeTiers(InvalidFeeTiers {}));
+        }
+        let max_fee_bps = U256::from(MAX_FEE_BPS);
+        if let Some(bps) = fee_bps.iter().find(|bps| **bps > max_fee_bps) {
+            return Err(Error::FeeTooHigh(FeeTooHigh {
+                feeBps: *bps,
+                maxFeeBps: max_fee_bps,
+            }));
+        }
+
+        let mut stored_min_volumes = self.fee_tier_min_volumes.setter(token);
+        stored_min_volumes.erase();
+        for min_volume in &min_volumes {
+            stored_min_volumes.push(*min_volume);
+        }
+        let mut stored_bps = self.fee_tier_bps.setter(token);
+        stored_bps.erase();
+        for bps in &fee_bps {
+            stored_bps.push(*bps);
+        }
+
+        log(
+            self.vm(),
+            FeeTiersUpdated {
+                token,
+                minVolumes: min_volumes,
+                feeBps: fee_bps,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Get the total fee collected in `token`, the zero address stands for native ETH
+    pub fn get_fees_collected(&self, token: Address) -> U256 {
+        self.fees_collected.get(token)
+    }
+
+    /// Get the largest number of recipients accepted in a single batch
+    pub fn max_batch_size(&self) -> U256 {
+        self.max_batch_size.get()
+    }
+
+    /// Set the largest number of recipients accepted in a single batch, at least one
+    /// The caller needs `CONFIG_ADMIN_ROLE`
+    pub fn set_max_batch_size(&mut self, max_batch_size: U256) -> Result<(), Error> {
+        self.access.only_role(CONFIG_ADMIN_ROLE.into())?;
+
+        if max_batch_size.is_zero() {
+            return Err(Error::InvalidMaxBatchSize(InvalidMaxBatchSize {}));
+        }
+
+        let previous = self.max_batch_size.get();
+        self.max_batch_size.set(max_batch_size);
+
+        log(
+            self.vm(),
+            MaxBatchSizeUpdated {
+                previousMaxBatchSize: previous,
+                newMaxBatchSize: max_batch_size,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Estimate how many recipients of `token` a batch can pay within `gas_limit`
+    /// The zero address stands for native ETH, the result never exceeds `max_batch_size`
+    /// Every recipient is priced at its worst case plus its calldata, and one more transfer
+    /// is set aside for the fee whenever `token` charges one to a sender without volume yet
+    /// `gas_limit` is the L2 execution budget, on Arbitrum the L1 data fee is billed as gas
+    /// on top of it and must be taken off first, e.g. with `gasEstimateL1Component`
+    /// Recipient contracts burning gas on receipt and tokens running extra hooks are not
+    /// covered, so keep headroom when paying those
+    pub fn max_recipients_for_gas(&self, gas_limit: U256, token: Address) -> U256 {
+        let per_transfer = if token.is_zero() {
+            NATIVE_TRANSFER_GAS
+        } else {
+            ERC20_TRANSFER_GAS
+        };
+        // Tier rates never exceed the rate at zero volume, so it tells whether a fee applies
+        let overhead = if self.tier_fee_bps(token, U256::ZERO).is_zero() {
+            BATCH_BASE_GAS
+        } else {
+            BATCH_BASE_GAS + per_transfer
+        };
+        let fits = gas_limit.saturating_sub(U256::from(overhead))
+            / U256::from(per_transfer + CALLDATA_GAS_PER_RECIPIENT);
+        fits.min(self.max_batch_size.get())
+    }
+
+    /// Pause every send path and `set_greeting`, `withdraw` stays available
+    /// The caller needs `PAUSER_ROLE`
+    pub fn pause(&mut self) -> Result<(), Error> {
+        self.access.only_role(PAUSER_ROLE.into())?;
+        Ok(self.pausable.pause()?)
+    }
+
+    /// Lift a previous `pause`
+    /// The caller needs `PAUSER_ROLE`
+    pub fn unpause(&mut self) -> Result<(), Error> {
+        self.access.only_role(PAUSER_ROLE.into())?;
+        Ok(self.pausable.unpause()?)
+    }
+
+    /// Identifier of the role allowed to `withdraw`
+    pub fn treasurer_role(&self) -> B256 {
+        TREASURER_ROLE.into()
+    }
+
+    /// Identifier of the role allowed to `pause` and `unpause`
+    pub fn pauser_role(&self) -> B256 {
+        PAUSER_ROLE.into()
+    }
+
+    /// Identifier of the role allowed to change contract settings
+    pub fn config_admin_role(&self) -> B256 {
+        CONFIG_ADMIN_ROLE.into()
+    }
+
+    /// Allow contract to receive ETH (equivalent to receive() function)
+    #[payable]
+    pub fn receive_ether(&self) {
+        // This function allows the contract to receive ETH
+        // The #[payable] attribute allows it to accept value
+    }
+}
+
+/// Internal helpers, not exposed in the ABI
+impl YourContract {
+    /// Pay out a single native transfer for `send_native_individual`
+    fn native_individual(&mut self, recipient: Address, amount: U256) -> Result<(), Error> {
+        batch::validate_recipient(self.vm(), 0, recipient)?;
+        let sender = self.vm().msg_sender();
+        let fee = self.fee_on(sender, Address::ZERO, amount);
+        transfers::require_msg_value(self.vm(), amount.saturating_add(fee))?;
+
+        // Transfer native token
+        self.push_native(sender, 0, recipient, amount);
+
+        self.record_native_sent(sender, amount);
+
+        // Emit event
+        log(
+            self.vm(),
+            NativeTokenSent {
+                from: sender,
+                to: recipient,
+                amount,
+            },
+        );
+
+        self.collect_fee(Address::ZERO, sender, fee)?;
+        Ok(transfers::refund_unspent_value(self.vm(), amount + fee)?)
+    }
+
+    /// Pay out a native batch for `send_native_batch`
+    fn native_batch(&mut self, recipients: Vec<Address>, amounts: Vec<U256>) -> Result<(), Error> {
+        self.validate_batch(&recipients, &amounts)?;
+        let sender = self.vm().msg_sender();
+        let total = sum_amounts(&amounts);
+        let fee = self.fee_on(sender, Address::ZERO, total);
+        transfers::require_msg_value(self.vm(), total.saturating_add(fee))?;
+
+        let mut total_amount = U256::ZERO;
+
+        // Send to each recipient
+        for (i, recipient) in recipients.iter().enumerate() {
+            let amount = amounts[i];
+            self.push_native(sender, i, *recipient, amount);
+            total_amount += amount;
+        }
+
+        self.record_native_sent(sender, total_amount);
+
+        // Emit event
+        log(
+            self.vm(),
+            BatchNativeTokenSent {
+                from: sender,
+                totalAmount: total_amount,
+                recipientCount: U256::from(recipients.len()),
+            },
+        );
+
+        self.collect_fee(Address::ZERO, sender, fee)?;
+        Ok(transfers::refund_unspent_value(
+            self.vm(),
+            total_amount + fee,
+        )?)
+    }
+
+    /// Pay out a native batch for `send_native_batch_atomic`
+    fn native_batch_atomic(
+        &mut self,
+        recipients: Vec<Address>,
+        amounts: Vec<U256>,
+    ) -> Result<(), Error> {
+        self.validate_batch(&recipients, &amounts)?;
+        let sender = self.vm().msg_sender();
+        let total = sum_amounts(&amounts);
+        let fee = self.fee_on(sender, Address::ZERO, total);
+        transfers::require_msg_value(self.vm(), total.saturating_add(fee))?;
+
+        let mut total_amount = U256::ZERO;
+
+        // Send to each recipient, stopping at the first failure
+        for (i, recipient) in recipients.iter().enumerate() {
+            let amount = amounts[i];
+            if self.vm().transfer_eth(*recipient, amount).is_err() {
+                return Err(Error::NativeTransferFailed(NativeTransferFailed {
+                    index: U256::from(i),
+                    recipient: *recipient,
+                    amount,
+                }));
+            }
+            total_amount += amount;
+        }
+
+        self.record_native_sent(sender, total_amount);
+
+        // Emit event
+        log(
+            self.vm(),
+            BatchNativeTokenSent {
+                from: sender,
+                totalAmount: total_amount,
+                recipientCount: U256::from(recipients.len()),
+            },
+        );
+
+        self.collect_fee(Address::ZERO, sender, fee)?;
+        Ok(transfers::refund_unspent_value(
+            self.vm(),
+            total_amount + fee,
+        )?)
+    }
+
+    /// Pay out a native batch for `send_native_batch_best_effort`
+    fn native_batch_best_effort(
+        &mut self,
+        recipients: Vec<Address>,
+        amounts: Vec<U256>,
+    ) -> Result<Vec<bool>, Error> {
+        self.validate_batch(&recipients, &amounts)?;
+        let sender = self.vm().msg_sender();
+        let total = sum_amounts(&amounts);
+        transfers::require_msg_value(
+            self.vm(),
+            total.saturating_add(self.fee_on(sender, Address::ZERO, total)),
+        )?;
+
+        let mut total_amount = U256::ZERO;
+        let mut sent_count = 0usize;
+        let mut results = Vec::with_capacity(recipients.len());
+
+        // Send to each recipient, reporting failures instead of aborting
+        for (i, recipient) in recipients.iter().enumerate() {
+            let amount = amounts[i];
+            let success = self.vm().transfer_eth(*recipient, amount).is_ok();
+            if success {
+                total_amount += amount;
+                sent_count += 1;
+            } else {
+                log(
+                    self.vm(),
+                    NativeTokenSendFailed {
+                        from: sender,
+                        to: *recipient,
+                        index: U256::from(i),
+                        amount,
+                    },
+                );
+            }
+            results.push(success);
+        }
+
+        // Rated on the volume before this batch, like every other send path
+        let fee = self.fee_on(sender, Address::ZERO, total_amount);
+        self.record_native_sent(sender, total_amount);
+
+        // Emit event
+        log(
+            self.vm(),
+            BatchNativeTokenSent {
+                from: sender,
+                totalAmount: total_amount,
+                recipientCount: U256::from(sent_count),
+            },
+        );
+
+        self.collect_fee(Address::ZERO, sender, fee)?;
+        transfers::refund_unspent_value(self.vm(), total_amount + fee)?;
+
+        Ok(results)
+    }
+
+    /// Pull an ERC-20 batch through Permit2 for `send_erc20_batch_with_permit2`
+    fn erc20_batch_with_permit2(
+        &mut self,
+        token: Address,
+        recipients: Vec<Address>,
+        amounts: Vec<U256>,
+        nonce: U256,
+        deadline: U256,
+        signature: Bytes,
+    ) -> Result<(), Error> {
+        self.validate_batch(&recipients, &amounts)?;
+
+        let permit2 = self.permit2.get();
+        if permit2.is_zero() || self.vm().code_size(permit2) == 0 {
+            return Err(Error::Permit2NotSet(Permit2NotSet {}));
+        }
+
+        let sender = self.vm().msg_sender();
+        let total_amount = sum_amounts(&amounts);
+        let fee = self.fee_on(sender, token, total_amount);
+
+        // The fee travels as one extra signed transfer straight to the fee recipient
+        let mut transfers: Vec<(Address, U256)> = recipients
+            .iter()
+            .copied()
+            .zip(amounts.iter().copied())
+            .collect();
+        if fee > U256::ZERO {
+            transfers.push((self.fee_recipient.get(), fee));
+        }
+        let permitted = transfers
+            .iter()
+            .map(|(_, amount)| IPermit2::TokenPermissions {
+                token,
+                amount: *amount,
+            })
+            .collect();
+        let transfer_details = transfers
+            .iter()
+            .map(|(to, amount)| IPermit2::SignatureTransferDetails {
+                to: *to,
+                requestedAmount: *amount,
+            })
+            .collect();
+        let call_data = IPermit2::permitBatchTransferFromCall {
+            permit: IPermit2::PermitBatchTransferFrom {
+                permitted,
+                nonce,
+                deadline,
+            },
+            transferDetails: transfer_details,
+            owner: sender,
+            signature: signature.0.into(),
+        }
+        .abi_encode();
+        if self.vm().call(&Call::new(), permit2, &call_data).is_err() {
+            return Err(Error::Permit2TransferFailed(Permit2TransferFailed {
+                token,
+                owner: sender,
+            }));
+        }
+
+        self.record_erc20_sent(token, sender, total_amount);
+        self.record_fee(token, sender, fee);
+
+        // Emit event
+        log(
+            self.vm(),
+            BatchERC20TokenSent {
+                token,
+                from: sender,
+                totalAmount: total_amount,
+                recipientCount: U256::from(recipients.len()),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Pay out a mixed token batch for `send_multi_token_batch`
+    fn multi_token_batch(
+        &mut self,
+        tokens: Vec<Address>,
+        recipients: Vec<Address>,
+        amounts: Vec<U256>,
+    ) -> Result<(), Error> {
+        if tokens.len() != recipients.len() {
+            return Err(Error::TokensLengthMismatch(TokensLengthMismatch {
+                tokensLength: U256::from(tokens.len()),
+                recipientsLength: U256::from(recipients.len()),
+            }));
+        }
+        self.validate_batch(&recipients, &amounts)?;
+
+        let native_total = tokens
+            .iter()
+            .zip(&amounts)
+            .filter(|(token, _)| token.is_zero())
+            .fold(U256::ZERO, |total, (_, amount)| {
+                total.saturating_add(*amount)
+            });
+        let sender = self.vm().msg_sender();
+        let native_fee = self.fee_on(sender, Address::ZERO, native_total);
+        transfers::require_msg_value(self.vm(), native_total.saturating_add(native_fee))?;
+
+        // (token, total amount, recipient count) in order of first appearance
+        let mut totals: Vec<(Address, U256, usize)> = Vec::new();
+
+        for (i, ((token, recipient), amount)) in
+            tokens.iter().zip(&recipients).zip(&amounts).enumerate()
+        {
+            let (token, recipient, amount) = (*token, *recipient, *amount);
+            if token.is_zero() {
+                if self.vm().transfer_eth(recipient, amount).is_err() {
+                    return Err(Error::NativeTransferFailed(NativeTransferFailed {
+                        index: U256::from(i),
+                        recipient,
+                        amount,
+                    }));
+                }
+                log(
+                    self.vm(),
+                    NativeTokenSent {
+                        from: sender,
+                        to: recipient,
+                        amount,
+                    },
+                );
+            } else {
+                transfers::transfer_erc20_from(self.vm(), token, sender, recipient, amount)?;
+                log(
+                    self.vm(),
+                    ERC20TokenSent {
+                        token,
+                        from: sender,
+                        to: recipient,
+                        amount,
+                    },
+                );
+            }
+
+            match totals.iter_mut().find(|(seen, _, _)| *seen == token) {
+                Some((_, total, count)) => {
+                    *total += amount;
+                    *count += 1;
+                }
+                None => totals.push((token, amount, 1)),
+            }
+        }
+
+        for (token, total_amount, count) in totals {
+            let fee = self.fee_on(sender, token, total_amount);
+            self.collect_fee(token, sender, fee)?;
+            if token.is_zero() {
+                self.record_native_sent(sender, total_amount);
+                log(
+                    self.vm(),
+                    BatchNativeTokenSent {
+                        from: sender,
+                        totalAmount: total_amount,
+                        recipientCount: U256::from(count),
+                    },
+                );
+            } else {
+                self.record_erc20_sent(token, sender, total_amount);
+                log(
+                    self.vm(),
+                    BatchERC20TokenSent {
+                        token,
+                        from: sender,
+                        totalAmount: total_amount,
+                        recipientCount: U256::from(count),
+                    },
+                );
+            }
+        }
+
+        Ok(transfers::refund_unspent_value(
+            self.vm(),
+            native_total + native_fee,
+        )?)
+    }
+
+    /// Send `amount` wei to `recipient`, crediting it to their `claimable` balance
+    /// if the transfer fails so the funds stay theirs
+    fn push_native(&mut self, sender: Address, index: usize, recipient: Address, amount: U256) {
+        if self.vm().transfer_eth(recipient, amount).is_ok() {
+            return;
+        }
+
+        let current = self.claimable.get(recipient);
+        self.claimable.insert(recipient, current + amount);
+        self.escrow(Address::ZERO, amount);
+
+        log(
+            self.vm(),
+            ClaimableCredited {
+                account: recipient,
+                from: sender,
+                index: U256::from(index),
+                amount,
+            },
+        );
+    }
+
+    /// Mark `amount` of `token` held by the contract as owed to users
+    fn escrow(&mut self, token: Address, amount: U256) {
+        let current = self.escrowed.get(token);
+        self.escrowed.insert(token, current + amount);
+    }
+
+    /// Release `amount` of `token` from escrow ahead of paying it out
+    fn release_escrow(&mut self, token: Address, amount: U256) {
+        let current = self.escrowed.get(token);
+        self.escrowed.insert(token, current - amount);
+    }
+
+    /// Native balance of the contract that is not held in escrow
+    fn available_native_balance(&self) -> U256 {
+        let balance = self.vm().balance(self.vm().contract_address());
+        balance.saturating_sub(self.escrowed.get(Address::ZERO))
+    }
+
+    /// Add `amount` to the global and per-sender native token counters
+    fn record_native_sent(&mut self, sender: Address, amount: U256) {
+        let current_total = self.total_native_sent.get();
+        self.total_native_sent.set(current_total + amount);
+
+        let current_user = self.user_native_sent.get(sender);
+        self.user_native_sent.insert(sender, current_user + amount);
+    }
+
+    /// Pull `amount` of `token` from `sender` to `recipient` and record it
+    fn erc20_individual(
+        &mut self,
+        token: Address,
+        sender: Address,
+        recipient: Address,
+        amount: U256,
+    ) -> Result<(), Error> {
+        batch::validate_recipient(self.vm(), 0, recipient)?;
+        transfers::transfer_erc20_from(self.vm(), token, sender, recipient, amount)?;
+        let fee = self.fee_on(sender, token, amount);
+        self.collect_fee(token, sender, fee)?;
+
+        self.record_erc20_sent(token, sender, amount);
+
+        // Emit event
+        log(
+            self.vm(),
+            ERC20TokenSent {
+                token,
+                from: sender,
+                to: recipient,
+                amount,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Pull `amounts` of `token` from `sender` to each of `recipients` and record them
+    fn erc20_batch(
+        &mut self,
+        token: Address,
+        sender: Address,
+        recipients: &[Address],
+        amounts: &[U256],
+    ) -> Result<(), Error> {
+        self.validate_batch(recipients, amounts)?;
+
+        let mut total_amount = U256::ZERO;
+
+        // Send to each recipient
+        for (recipient, amount) in recipients.iter().zip(amounts) {
+            transfers::transfer_erc20_from(self.vm(), token, sender, *recipient, *amount)?;
+            total_amount += *amount;
+        }
+        let fee = self.fee_on(sender, token, total_amount);
+        self.collect_fee(token, sender, fee)?;
+
+        self.record_erc20_sent(token, sender, total_amount);
+
+        // Emit event
+        log(
+            self.vm(),
+            BatchERC20TokenSent {
+                token,
+                from: sender,
+                totalAmount: total_amount,
+                recipientCount: U256::from(recipients.len()),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Submit an EIP-2612 `permit` letting this contract spend `value` of `owner`'s tokens
+    /// and revert unless the allowance then covers `required`
+    /// A failed `permit` is tolerated when the allowance is already in place, so a front-run
+    /// of the same signature cannot block the transfer, and a successful one is not trusted
+    /// on its own since tokens with a non-reverting fallback such as WETH9 accept any call
+    #[allow(clippy::too_many_arguments)]
+    fn permit(
+        &mut self,
+        token: Address,
+        owner: Address,
+        value: U256,
+        deadline: U256,
+        v: u8,
+        r: B256,
+        s: B256,
+        required: U256,
+    ) -> Result<(), Error> {
+        if self.vm().code_size(token) == 0 {
+            return Err(Error::PermitFailed(PermitFailed { token, owner }));
+        }
+
+        let spender = self.vm().contract_address();
+        let call_data = IERC20::permitCall {
+            owner,
+            spender,
+            value,
+            deadline,
+            v,
+            r,
+            s,
+        }
+        .abi_encode();
+        // Only the resulting allowance tells whether the permit took effect
+        let _ = self.vm().call(&Call::new(), token, &call_data);
+
+        if transfers::erc20_allowance(self.vm(), token, owner, spender) >= required {
+            return Ok(());
+        }
+        Err(Error::PermitFailed(PermitFailed { token, owner }))
+    }
+
+    /// Send `amount` wei of the contract balance to `recipient`, reverting if the transfer fails
+    fn withdraw_native(&mut self, recipient: Address, amount: U256) -> Result<(), Error> {
+        self.vm()
+            .transfer_eth(recipient, amount)
+            .map_err(|_| Error::WithdrawFailed(WithdrawFailed { recipient, amount }))?;
+
+        log(
+            self.vm(),
+            Withdrawn {
+                token: Address::ZERO,
+                to: recipient,
+                amount,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Revert unless `recipients` and `amounts` describe a non-empty batch of matching length
+    /// no larger than `max_batch_size`, paying only valid recipients
+    fn validate_batch(&self, recipients: &[Address], amounts: &[U256]) -> Result<(), Error> {
+        Ok(batch::validate(
+            self.vm(),
+            recipients,
+            amounts,
+            self.max_batch_size.get(),
+        )?)
+    }
+    /// Revert if a batch of `size` recipients exceeds `max_batch_size`
+    fn check_batch_size(&self, size: usize) -> Result<(), Error> {
+        Ok(batch::check_size(size, self.max_batch_size.get())?)
+    }
+    /// Revert with the index of the first repeated recipient, if any
+    /// The size limit is enforced first so an oversized batch is never sorted
+    fn require_unique(&self, recipients: &[Address]) -> Result<(), Error> {
+        self.check_batch_size(recipients.len())?;
+        match duplicate_indices(recipients).first() {
+            Some(index) => Err(Error::DuplicateRecipient(DuplicateRecipient {
+                index: U256::from(*index),
+            })),
+            None => Ok(()),
+        }
+    }
+
+    /// Revert if withdrawing to `recipient` would burn the funds or leave them in the contract
+    fn validate_withdraw_recipient(&self, recipient: Address) -> Result<(), Error> {
+        if recipient.is_zero() || recipient == self.vm().contract_address() {
+            return Err(Error::InvalidWithdrawRecipient(InvalidWithdrawRecipient {
+                recipient,
+            }));
+        }
+        Ok(())
+    }
+
+    /// Add `amount` of `token` to the aggregate and per-token ERC-20 counters
+    fn record_erc20_sent(&mut self, token: Address, sender: Address, amount: U256) {
+        let current_total = self.total_erc20_sent.get();
+        self.total_erc20_sent.set(current_total + amount);
+
+        let current_user = self.user_erc20_sent.get(sender);
+        self.user_erc20_sent.insert(sender, current_user + amount);
+
+        let current_token_total = self.token_total_sent.get(token);
+        self.token_total_sent
+            .insert(token, current_token_total + amount);
+
+        let mut token_user_sent = self.token_user_sent.setter(token);
+        let current_token_user = token_user_sent.get(sender);
+        token_user_sent.insert(sender, current_token_user + amount);
+    }
+
+    /// Protocol fee `sender` owes on top of sending `total` of `token`, rounded down
+    fn fee_on(&self, sender: Address, token: Address, total: U256) -> U256 {
+        apply_bps(total, self.effective_fee_bps(sender, token))
+    }
+
+    /// Fee rate in basis points for a non-exempt sender with `volume` already sent in `token`
+    fn tier_fee_bps(&self, token: Address, volume: U256) -> U256 {
+        let base_bps = self.fee_bps.get();
+        let min_volumes = self.fee_tier_min_volumes.getter(token);
+        let tier_bps = self.fee_tier_bps.getter(token);
+        // Tiers are stored in ascending order of minimum volume
+        // Capping at the base rate here rather than in `set_fee_tiers` keeps tiers a discount
+        // after the base rate is lowered, and keeps every fee at zero while no fee recipient
+        // is configured, since `set_fee` only accepts a zero recipient with a zero rate
+        (0..min_volumes.len())
+            .rev()
+            .find(|&i| min_volumes.get(i).unwrap_or(U256::MAX) <= volume)
+            .and_then(|i| tier_bps.get(i))
+            .map_or(base_bps, |bps| bps.min(base_bps))
+    }
+
+    /// Pay `fee` in `token` from `sender` to the fee recipient and record it
+    /// Native fees come out of `msg.value` and are credited to the fee recipient's `claimable`
+    /// balance, so a recipient refusing ETH cannot block sends, ERC-20 fees are pulled from `sender`
+    fn collect_fee(&mut self, token: Address, sender: Address, fee: U256) -> Result<(), Error> {
+        if fee.is_zero() {
+            return Ok(());
+        }
+
+        let fee_recipient = self.fee_recipient.get();
+        if token.is_zero() {
+            let current = self.claimable.get(fee_recipient);
+            self.claimable.insert(fee_recipient, current + fee);
+            self.escrow(Address::ZERO, fee);
+        } else {
+            transfers::transfer_erc20_from(self.vm(), token, sender, fee_recipient, fee)?;
+        }
+
+        self.record_fee(token, sender, fee);
+        Ok(())
+    }
+
+    /// Add an already paid `fee` to the per-token fee counter and emit `FeeCollected`
+    fn record_fee(&mut self, token: Address, sender: Address, fee: U256) {
+        if fee.is_zero() {
+            return;
+        }
+
+        let current = self.fees_collected.get(token);
+        self.fees_collected.insert(token, current + fee);
+
+        log(
+            self.vm(),
+            FeeCollected {
+                token,
+                from: sender,
+                feeRecipient: self.fee_recipient.get(),
+                amount: fee,
+            },
+        );
+    }
+}
+
+/// Indices of the entries of `recipients` repeating an earlier one, in ascending order
+fn duplicate_indices(recipients: &[Address]) -> Vec<usize> {
+    // Sorting keeps this O(n log n) for batches of a few hundred recipients
+    let mut sorted: Vec<(Address, usize)> = recipients.iter().copied().zip(0..).collect();
+    sorted.sort_unstable();
+    let mut duplicates: Vec<usize> = sorted
+        .windows(2)
+        .filter(|pair| pair[0].0 == pair[1].0)
+        .map(|pair| pair[1].1)
+        .collect();
+    duplicates.sort_unstable();
+    duplicates
+}
+
+/// Take `bps` basis points of `total`, rounded down
+fn apply_bps(total: U256, bps: U256) -> U256 {
+    let denominator = U256::from(BPS_DENOMINATOR);
+    // Split the product so it cannot overflow for any `total`
+    total / denominator * bps + total % denominator * bps / denominator
+}
+
+impl NonReentrant for YourContract {
+    fn reentrancy_guard(&mut self) -> &mut ReentrancyGuard {
+        &mut self.reentrancy_guard
+    }
+}
+
+/// Implementation of the IOwnable2Step interface, a superset of the IOwnable ABI
+/// `transfer_ownership` only nominates `new_owner`, who must call `accept_ownership`
+#[public]
+impl IOwnable2Step for YourContract {
+    type Error = Error;
+
+    fn owner(&self) -> Address {
+        self.ownable.owner()
+    }
+
+    fn pending_owner(&self) -> Address {
+        self.ownable.pending_owner()
+    }
+
+    fn transfer_ownership(&mut self, new_owner: Address) -> Result<(), Self::Error> {
+        Ok(self.ownable.transfer_ownership(new_owner)?)
+    }
+
+    fn accept_ownership(&mut self) -> Result<(), Self::Error> {
+        Ok(self.ownable.accept_ownership()?)
+    }
+
+    fn renounce_ownership(&mut self) -> Result<(), Self::Error> {
+        Ok(self.ownable.renounce_ownership()?)
+    }
+}
+
+/// Implementation of the IAccessControl interface
+/// Roles are independent of ownership, transferring ownership does not move them
+#[public]
+impl IAccessControl for YourContract {
+    type Error = Error;
+
+    fn has_role(&self, role: B256, account: Address) -> bool {
+        self.access.has_role(role, account)
+    }
+
+    fn only_role(&self, role: B256) -> Result<(), Self::Error> {
+        Ok(self.access.only_role(role)?)
+    }
+
+    fn get_role_admin(&self, role: B256) -> B256 {
+        self.access.get_role_admin(role)
+    }
+
+    fn grant_role(&mut self, role: B256, account: Address) -> Result<(), Self::Error> {
+        Ok(self.access.grant_role(role, account)?)
+    }
+
+    fn revoke_role(&mut self, role: B256, account: Address) -> Result<(), Self::Error> {
+        Ok(self.access.revoke_role(role, account)?)
+    }
+
+    fn renounce_role(&mut self, role: B256, confirmation: Address) -> Result<(), Self::Error> {
+        Ok(self.access.renounce_role(role, confirmation)?)
+    }
+}
+
+/// Implementation of the IPausable interface
+#[public]
+impl IPausable for YourContract {
+    fn paused(&self) -> bool {
+        self.pausable.paused()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Error;
+    use super::*;
+    use stylus_sdk::testing::*;
+
+    /// Caller seen by OpenZeppelin components, which read the `msg_sender` hostio directly
+    const HOSTIO_SENDER: Address = Address::new([1u8; 20]);
+
+    #[no_mangle]
+    pub unsafe extern "C" fn emit_log(_pointer: *const u8, _len: usize, _: usize) {}
+    #[no_mangle]
+    pub unsafe extern "C" fn msg_sender(sender: *mut u8) {
+        core::ptr::copy_nonoverlapping(HOSTIO_SENDER.as_ptr(), sender, 20);
+    }
+
+    /// Simulate a call carrying `value` wei, crediting it to the contract like the EVM would
+    fn fund_call(vm: &TestVM, value: U256) {
+        vm.set_value(value);
+        vm.set_balance(
+            vm.contract_address(),
+            vm.balance(vm.contract_address()) + value,
+        );
+    }
+
+    #[test]
+    #[allow(clippy::bool_assert_comparison)]
+    fn test_your_contract() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+
+        // Test initialization
+        let owner_addr = Address::from([1u8; 20]);
+        let _ = contract.constructor(owner_addr);
+
+        assert_eq!(contract.owner(), owner_addr);
+        assert_eq!(contract.greeting(), "Building Unstoppable Apps!!!");
+        assert_eq!(contract.premium(), false);
+        assert_eq!(contract.total_counter(), U256::ZERO);
+
+        // Test setting greeting without payment
+        contract.set_greeting("Hello World".to_string()).unwrap();
+        assert_eq!(contract.greeting(), "Hello World");
+        assert_eq!(contract.premium(), false);
+        assert_eq!(contract.total_counter(), U256::from(1));
+
+        // Test user greeting counter
+        let sender = vm.msg_sender();
+        assert_eq!(contract.user_greeting_counter(sender), U256::from(1));
+
+        // Test setting greeting with payment
+        vm.set_value(U256::from(100));
+        contract.set_greeting("Premium Hello".to_string()).unwrap();
+        assert_eq!(contract.greeting(), "Premium Hello");
+        assert_eq!(contract.premium(), true);
+        assert_eq!(contract.total_counter(), U256::from(2));
+        assert_eq!(contract.user_greeting_counter(sender), U256::from(2));
+    }
+
+    #[test]
+    fn test_send_native_batch_atomic() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+        let _ = contract.constructor(Address::from([1u8; 20]));
+
+        let alice = Address::from([2u8; 20]);
+        let bob = Address::from([3u8; 20]);
+        fund_call(&vm, U256::from(200));
+
+        // Bob's balance cannot be credited, which aborts the batch
+        vm.set_balance(bob, U256::MAX);
+        let err = contract
+            .send_native_batch_atomic(vec![alice, bob], vec![U256::from(100), U256::from(100)])
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::NativeTransferFailed(NativeTransferFailed { index, recipient, .. })
+                if index == U256::from(1) && recipient == bob
+        ));
+        assert_eq!(contract.get_total_native_sent(), U256::ZERO);
+
+        vm.set_balance(bob, U256::ZERO);
+        fund_call(&vm, U256::from(50));
+        contract
+            .send_native_batch_atomic(vec![alice, bob], vec![U256::from(20), U256::from(30)])
+            .unwrap();
+        assert_eq!(contract.get_total_native_sent(), U256::from(50));
+        assert_eq!(
+            contract.get_user_native_sent(vm.msg_sender()),
+            U256::from(50)
+        );
+    }
+
+    #[test]
+    fn test_send_native_batch_best_effort() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+        let _ = contract.constructor(Address::from([1u8; 20]));
+
+        let alice = Address::from([2u8; 20]);
+        let bob = Address::from([3u8; 20]);
+        let carol = Address::from([4u8; 20]);
+        fund_call(&vm, U256::from(250));
+
+        // Bob's balance cannot be credited, Carol's transfer still lands
+        vm.set_balance(bob, U256::MAX);
+        let results = contract
+            .send_native_batch_best_effort(
+                vec![alice, bob, carol],
+                vec![U256::from(100), U256::from(100), U256::from(50)],
+            )
+            .unwrap();
+        assert_eq!(results, vec![true, false, true]);
+        assert_eq!(vm.balance(carol), U256::from(50));
+        // Bob's share goes back to the sender
+        assert_eq!(vm.balance(vm.msg_sender()), U256::from(100));
+        assert_eq!(contract.get_total_native_sent(), U256::from(150));
+        assert_eq!(
+            contract.get_user_native_sent(vm.msg_sender()),
+            U256::from(150)
+        );
+    }
+
+    #[test]
+    fn test_send_native_value_checks() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+        let _ = contract.constructor(Address::from([1u8; 20]));
+
+        let alice = Address::from([2u8; 20]);
+        let bob = Address::from([3u8; 20]);
+
+        // Underpaying reverts before anything is sent
+        fund_call(&vm, U256::from(50));
+        let err = contract
+            .send_native_batch(vec![alice, bob], vec![U256::from(40), U256::from(40)])
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InsufficientValue(InsufficientValue { required, provided })
+                if required == U256::from(80) && provided == U256::from(50)
+        ));
+        assert_eq!(vm.balance(alice), U256::ZERO);
+
+        // Overpaying refunds the surplus to the sender
+        fund_call(&vm, U256::from(100));
+        contract
+            .send_native_individual(alice, U256::from(60))
+            .unwrap();
+        assert_eq!(vm.balance(alice), U256::from(60));
+        assert_eq!(vm.balance(vm.msg_sender()), U256::from(40));
+    }
+
+    #[test]
+    fn test_batch_shape_errors() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+        let _ = contract.constructor(Address::from([1u8; 20]));
+
+        let token = Address::from([9u8; 20]);
+        let alice = Address::from([2u8; 20]);
+        let bob = Address::from([3u8; 20]);
+
+        let err = contract
+            .send_native_batch(vec![alice, bob], vec![U256::from(1)])
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::LengthMismatch(LengthMismatch { recipientsLength, amountsLength })
+                if recipientsLength == U256::from(2) && amountsLength == U256::from(1)
+        ));
+
+        let err = contract
+            .send_erc20_batch(token, vec![alice], vec![])
+            .unwrap_err();
+        assert!(matches!(err, Error::LengthMismatch(_)));
+
+        let err = contract
+            .send_native_batch_best_effort(vec![], vec![])
+            .unwrap_err();
+        assert!(matches!(err, Error::EmptyBatch(_)));
+
+        let err = contract
+            .send_erc20_batch(token, vec![], vec![])
+            .unwrap_err();
+        assert!(matches!(err, Error::EmptyBatch(_)));
+    }
+
+    #[test]
+    fn test_send_erc20_return_data() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+        let _ = contract.constructor(Address::from([1u8; 20]));
+
+        let token = Address::from([9u8; 20]);
+        let alice = Address::from([2u8; 20]);
+        let sender = vm.msg_sender();
+        let amount = U256::from(10);
+        let call_data = IERC20::transferFromCall {
+            from: sender,
+            to: alice,
+            amount,
+        }
+        .abi_encode();
+
+        // No code at the token address
+        let err = contract
+            .send_erc20_individual(token, alice, amount)
+            .unwrap_err();
+        assert!(matches!(err, Error::Erc20CallFailed(_)));
+        vm.set_code(token, vec![0x00]);
+
+        // USDT-style tokens return nothing
+        contract
+            .send_erc20_individual(token, alice, amount)
+            .unwrap();
+
+        vm.mock_call(
+            token,
+            call_data.clone(),
+            Ok(U256::from(1).to_be_bytes_vec()),
+        );
+        contract
+            .send_erc20_batch(token, vec![alice], vec![amount])
+            .unwrap();
+        assert_eq!(contract.get_user_erc20_sent(sender), U256::from(20));
+
+        // Only the first word counts, trailing bytes are ignored but a short word is not
+        let mut padded = U256::from(1).to_be_bytes_vec();
+        padded.extend_from_slice(&[0xff; 32]);
+        vm.mock_call(token, call_data.clone(), Ok(padded));
+        contract
+            .send_erc20_individual(token, alice, amount)
+            .unwrap();
+        vm.mock_call(token, call_data.clone(), Ok(vec![0x01]));
+        let err = contract
+            .send_erc20_individual(token, alice, amount)
+            .unwrap_err();
+        assert!(matches!(err, Error::Erc20InvalidReturnData(_)));
+
+        vm.mock_call(token, call_data.clone(), Ok(U256::ZERO.to_be_bytes_vec()));
+        let err = contract
+            .send_erc20_individual(token, alice, amount)
+            .unwrap_err();
+        assert!(matches!(err, Error::Erc20TransferReturnedFalse(_)));
+
+        vm.mock_call(token, call_data, Err(vec![]));
+        let err = contract
+            .send_erc20_batch(token, vec![alice], vec![amount])
+            .unwrap_err();
+        assert!(matches!(err, Error::Erc20CallFailed(_)));
+        assert_eq!(contract.get_user_erc20_sent(sender), U256::from(30));
+    }
+
+    #[test]
+    fn test_per_token_accounting() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+        let _ = contract.constructor(Address::from([1u8; 20]));
+
+        let usdc = Address::from([8u8; 20]);
+        let weth = Address::from([9u8; 20]);
+        let alice = Address::from([2u8; 20]);
+        let sender = vm.msg_sender();
+        vm.set_code(usdc, vec![0x00]);
+        vm.set_code(weth, vec![0x00]);
+
+        contract
+            .send_erc20_individual(usdc, alice, U256::from(1_000_000))
+            .unwrap();
+        contract
+            .send_erc20_batch(weth, vec![alice], vec![U256::from(10).pow(U256::from(18))])
+            .unwrap();
+
+        assert_eq!(
+            contract.get_total_sent_for_token(usdc),
+            U256::from(1_000_000)
+        );
+        assert_eq!(
+            contract.get_user_sent_for_token(weth, sender),
+            U256::from(10).pow(U256::from(18))
+        );
+        assert_eq!(contract.get_user_sent_for_token(usdc, alice), U256::ZERO);
+    }
+
+    #[test]
+    fn test_send_erc20_with_permit() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+        let _ = contract.constructor(Address::from([1u8; 20]));
+
+        let token = Address::from([9u8; 20]);
+        let alice = Address::from([2u8; 20]);
+        let bob = Address::from([3u8; 20]);
+        let owner = vm.msg_sender();
+        let spender = vm.contract_address();
+        let (value, deadline) = (U256::from(30), U256::from(1_000));
+        let (r, s) = (B256::repeat_byte(0x11), B256::repeat_byte(0x22));
+        vm.set_code(token, vec![0x00]);
+        let permit_call = IERC20::permitCall {
+            owner,
+            spender,
+            value,
+            deadline,
+            v: 27,
+            r,
+            s,
+        }
+        .abi_encode();
+        let allowance_call = IERC20::allowanceCall { owner, spender }.abi_encode();
+
+        // WETH9-style fallback accepts the permit call without setting an allowance
+        let err = contract
+            .send_erc20_individual_with_permit(token, alice, value, value, deadline, 27, r, s)
+            .unwrap_err();
+        assert!(matches!(err, Error::PermitFailed(_)));
+        assert_eq!(contract.get_total_sent_for_token(token), U256::ZERO);
+
+        // Token without permit support and no allowance
+        vm.mock_call(token, permit_call, Err(vec![]));
+        let err = contract
+            .send_erc20_individual_with_permit(token, alice, value, value, deadline, 27, r, s)
+            .unwrap_err();
+        assert!(matches!(err, Error::PermitFailed(_)));
+
+        // Permit already consumed by a front-runner, the allowance is still in place
+        vm.mock_static_call(token, allowance_call.clone(), Ok(value.to_be_bytes_vec()));
+        contract
+            .send_erc20_individual_with_permit(token, alice, value, value, deadline, 27, r, s)
+            .unwrap();
+
+        // Token accepts the permit and sets the allowance
+        vm.clear_mocks();
+        vm.mock_static_call(token, allowance_call, Ok(value.to_be_bytes_vec()));
+        contract
+            .send_erc20_batch_with_permit(
+                token,
+                vec![alice, bob],
+                vec![U256::from(10), U256::from(20)],
+                value,
+                deadline,
+                27,
+                r,
+                s,
+            )
+            .unwrap();
+        assert_eq!(
+            contract.get_total_sent_for_token(token),
+            value + U256::from(30)
+        );
+    }
+
+    #[test]
+    fn test_send_erc20_batch_with_permit2() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+        let owner = HOSTIO_SENDER;
+        vm.set_sender(owner);
+        contract.constructor(owner).unwrap();
+        assert_eq!(contract.permit2(), CANONICAL_PERMIT2);
+
+        let token = Address::from([9u8; 20]);
+        let alice = Address::from([2u8; 20]);
+        let bob = Address::from([3u8; 20]);
+        let recipients = vec![alice, bob];
+        let amounts = vec![U256::from(10), U256::from(20)];
+        let (nonce, deadline) = (U256::from(7), U256::from(1_000));
+        let signature = Bytes(vec![0xab; 65]);
+
+        // Canonical Permit2 is not deployed on this chain
+        let err = contract
+            .send_erc20_batch_with_permit2(
+                token,
+                recipients.clone(),
+                amounts.clone(),
+                nonce,
+                deadline,
+                signature.clone(),
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::Permit2NotSet(_)));
+
+        // Local Permit2 stand-in
+        let permit2 = Address::from([5u8; 20]);
+        vm.set_code(permit2, vec![0x00]);
+        contract.set_permit2(permit2).unwrap();
+        contract
+            .send_erc20_batch_with_permit2(
+                token,
+                recipients.clone(),
+                amounts.clone(),
+                nonce,
+                deadline,
+                signature.clone(),
+            )
+            .unwrap();
+        assert_eq!(contract.get_total_sent_for_token(token), U256::from(30));
+
+        // Permit2 rejects a reused nonce
+        let call_data = IPermit2::permitBatchTransferFromCall {
+            permit: IPermit2::PermitBatchTransferFrom {
+                permitted: amounts
+                    .iter()
+                    .map(|amount| IPermit2::TokenPermissions {
+                        token,
+                        amount: *amount,
+                    })
+                    .collect(),
+                nonce,
+                deadline,
+            },
+            transferDetails: recipients
+                .iter()
+                .zip(&amounts)
+                .map(|(to, amount)| IPermit2::SignatureTransferDetails {
+                    to: *to,
+                    requestedAmount: *amount,
+                })
+                .collect(),
+            owner,
+            signature: signature.0.clone().into(),
+        }
+        .abi_encode();
+        vm.mock_call(permit2, call_data, Err(vec![]));
+        let err = contract
+            .send_erc20_batch_with_permit2(token, recipients, amounts, nonce, deadline, signature)
+            .unwrap_err();
+        assert!(matches!(err, Error::Permit2TransferFailed(_)));
+    }
+
+    #[test]
+    fn test_send_multi_token_batch() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+        let _ = contract.constructor(Address::from([1u8; 20]));
+
+        let usdc = Address::from([8u8; 20]);
+        let arb = Address::from([9u8; 20]);
+        let alice = Address::from([2u8; 20]);
+        let bob = Address::from([3u8; 20]);
+        let sender = vm.msg_sender();
+        vm.set_code(usdc, vec![0x00]);
+        vm.set_code(arb, vec![0x00]);
+
+        let err = contract
+            .send_multi_token_batch(vec![usdc], vec![alice, bob], vec![U256::from(1); 2])
+            .unwrap_err();
+        assert!(matches!(err, Error::TokensLengthMismatch(_)));
+
+        fund_call(&vm, U256::from(100));
+        vm.clear_mocks();
+        contract
+            .send_multi_token_batch(
+                vec![usdc, Address::ZERO, arb, usdc, Address::ZERO],
+                vec![alice, alice, alice, bob, bob],
+                vec![
+                    U256::from(5),
+                    U256::from(30),
+                    U256::from(7),
+                    U256::from(6),
+                    U256::from(40),
+                ],
+            )
+            .unwrap();
+
+        assert_eq!(vm.balance(alice), U256::from(30));
+        assert_eq!(vm.balance(bob), U256::from(40));
+        assert_eq!(vm.balance(sender), U256::from(30));
+        assert_eq!(contract.get_user_native_sent(sender), U256::from(70));
+        assert_eq!(
+            contract.get_user_sent_for_token(usdc, sender),
+            U256::from(11)
+        );
+        assert_eq!(contract.get_user_sent_for_token(arb, sender), U256::from(7));
+        // Five per-item events plus one aggregate per token
+        assert_eq!(vm.get_emitted_logs().len(), 8);
+    }
+
+    #[test]
+    fn test_reentrancy_guard() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+        let _ = contract.constructor(Address::from([1u8; 20]));
+
+        let alice = Address::from([2u8; 20]);
+        let token = Address::from([9u8; 20]);
+        vm.set_code(token, vec![0x00]);
+
+        // The guard is released after every call, successful or not
+        fund_call(&vm, U256::from(20));
+        contract
+            .send_native_batch(vec![alice], vec![U256::from(10)])
+            .unwrap();
+        assert!(!contract.reentrancy_guard.entered());
+        contract.send_native_batch(vec![], vec![]).unwrap_err();
+        assert!(!contract.reentrancy_guard.entered());
+
+        // Including a revert from inside the transfer loop
+        let bob = Address::from([3u8; 20]);
+        vm.set_balance(bob, U256::MAX);
+        fund_call(&vm, U256::from(20));
+        let err = contract
+            .send_native_batch_atomic(vec![bob, alice], vec![U256::from(10); 2])
+            .unwrap_err();
+        assert!(matches!(err, Error::NativeTransferFailed(_)));
+        assert!(!contract.reentrancy_guard.entered());
+
+        // TestVM settles `transfer_eth` and mocked calls without running any recipient
+        // code, so a recipient cannot call back into the contract here. Holding the guard
+        // reproduces the state such a callback observes mid-transfer, and every guarded
+        // entrypoint must reject it with `ReentrantCall` before moving funds
+        fn assert_reentrant<T>(result: Result<T, Error>) {
+            assert!(matches!(result, Err(Error::ReentrantCall(_))));
+        }
+
+        contract.reentrancy_guard.enter().unwrap();
+        let one = vec![U256::from(10)];
+        fund_call(&vm, U256::from(10));
+        assert_reentrant(contract.send_native_individual(alice, U256::from(10)));
+        assert_reentrant(contract.send_native_batch(vec![alice], one.clone()));
+        assert_reentrant(contract.send_native_batch_atomic(vec![alice], one.clone()));
+        assert_reentrant(contract.send_native_batch_best_effort(vec![alice], one.clone()));
+        assert_reentrant(contract.send_native_batch_unique(vec![alice], one.clone()));
+        assert_reentrant(contract.send_multi_token_batch(
+            vec![Address::ZERO, token],
+            vec![alice, bob],
+            vec![U256::from(10); 2],
+        ));
+
+        vm.set_value(U256::ZERO);
+        assert_reentrant(contract.send_erc20_individual(token, alice, U256::from(10)));
+        assert_reentrant(contract.send_erc20_batch(token, vec![alice], one.clone()));
+        assert_reentrant(contract.send_erc20_batch_unique(token, vec![alice], one.clone()));
+        assert_reentrant(contract.send_erc20_individual_with_permit(
+            token,
+            alice,
+            U256::from(10),
+            U256::from(10),
+            U256::MAX,
+            27,
+            B256::ZERO,
+            B256::ZERO,
+        ));
+        assert_reentrant(contract.send_erc20_batch_with_permit(
+            token,
+            vec![alice],
+            one.clone(),
+            U256::from(10),
+            U256::MAX,
+            27,
+            B256::ZERO,
+            B256::ZERO,
+        ));
+        assert_reentrant(contract.send_erc20_batch_with_permit2(
+            token,
+            vec![alice],
+            one,
+            U256::ZERO,
+            U256::MAX,
+            Bytes(vec![0xab; 65]),
+        ));
+
+        assert_reentrant(contract.withdraw());
+        assert_reentrant(contract.withdraw_to(alice, U256::from(1)));
+        assert_reentrant(contract.withdraw_erc20(token, alice, U256::from(1)));
+        assert_reentrant(contract.claim());
+        assert_eq!(vm.balance(alice), U256::from(10));
+        assert_eq!(contract.get_total_native_sent(), U256::from(10));
+        assert_eq!(contract.get_total_erc20_sent(), U256::ZERO);
+    }
+
+    #[test]
+    fn test_pause() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+        let _ = contract.constructor(HOSTIO_SENDER);
+
+        let alice = Address::from([2u8; 20]);
+        let token = Address::from([9u8; 20]);
+        vm.set_code(token, vec![0x00]);
+
+        contract.pause().unwrap();
+        assert!(contract.paused());
+        assert!(matches!(
+            contract.pause().unwrap_err(),
+            Error::EnforcedPause(_)
+        ));
+
+        fund_call(&vm, U256::from(10));
+        let err = contract
+            .send_native_individual(alice, U256::from(10))
+            .unwrap_err();
+        assert!(matches!(err, Error::EnforcedPause(_)));
+        let err = contract
+            .send_erc20_batch(token, vec![alice], vec![U256::from(10)])
+            .unwrap_err();
+        assert!(matches!(err, Error::EnforcedPause(_)));
+        let err = contract.set_greeting("Paused".to_string()).unwrap_err();
+        assert!(matches!(err, Error::EnforcedPause(_)));
+
+        // The treasurer can still drain the contract while paused
+        contract.withdraw().unwrap();
+        assert_eq!(vm.balance(HOSTIO_SENDER), U256::from(10));
+
+        contract.unpause().unwrap();
+        assert!(!contract.paused());
+        contract.set_greeting("Unpaused".to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_access_control_roles() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+        let _ = contract.constructor(HOSTIO_SENDER);
+
+        let alice = Address::from([2u8; 20]);
+        let admin: B256 = AccessControl::DEFAULT_ADMIN_ROLE.into();
+        for role in [
+            admin,
+            contract.treasurer_role(),
+            contract.pauser_role(),
+            contract.config_admin_role(),
+        ] {
+            assert!(contract.has_role(role, HOSTIO_SENDER));
+            assert!(!contract.has_role(role, alice));
+            assert_eq!(contract.get_role_admin(role), admin);
+        }
+
+        // Each role gates its own entrypoint
+        contract
+            .revoke_role(TREASURER_ROLE.into(), HOSTIO_SENDER)
+            .unwrap();
+        assert!(matches!(
+            contract.withdraw().unwrap_err(),
+            Error::AccessControlUnauthorizedAccount(_)
+        ));
+        contract
+            .renounce_role(PAUSER_ROLE.into(), HOSTIO_SENDER)
+            .unwrap();
+        assert!(matches!(
+            contract.pause().unwrap_err(),
+            Error::AccessControlUnauthorizedAccount(_)
+        ));
+        contract
+            .revoke_role(CONFIG_ADMIN_ROLE.into(), HOSTIO_SENDER)
+            .unwrap();
+        assert!(matches!(
+            contract.set_permit2(alice).unwrap_err(),
+            Error::AccessControlUnauthorizedAccount(_)
+        ));
+
+        // Ownership alone no longer grants withdraw rights
+        assert_eq!(contract.owner(), HOSTIO_SENDER);
+
+        contract
+            .grant_role(TREASURER_ROLE.into(), HOSTIO_SENDER)
+            .unwrap();
+        contract.withdraw().unwrap();
+
+        // Renouncing on behalf of someone else is rejected
+        assert!(matches!(
+            contract.renounce_role(admin, alice).unwrap_err(),
+            Error::AccessControlBadConfirmation(_)
+        ));
+
+        // Without the admin role nothing can be granted any more
+        contract.renounce_role(admin, HOSTIO_SENDER).unwrap();
+        assert!(matches!(
+            contract.grant_role(PAUSER_ROLE.into(), alice).unwrap_err(),
+            Error::AccessControlUnauthorizedAccount(_)
+        ));
+    }
+
+    #[test]
+    fn test_two_step_ownership() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+        let _ = contract.constructor(HOSTIO_SENDER);
+
+        let alice = Address::from([2u8; 20]);
+
+        // Nominating a new owner leaves the current one in charge
+        contract.transfer_ownership(alice).unwrap();
+        assert_eq!(contract.owner(), HOSTIO_SENDER);
+        assert_eq!(contract.pending_owner(), alice);
+
+        // Only the pending owner may accept
+        assert!(matches!(
+            contract.accept_ownership().unwrap_err(),
+            Error::UnauthorizedAccount(_)
+        ));
+
+        // Re-nominating replaces the pending owner, which can then accept
+        contract.transfer_ownership(HOSTIO_SENDER).unwrap();
+        assert_eq!(contract.pending_owner(), HOSTIO_SENDER);
+        contract.accept_ownership().unwrap();
+        assert_eq!(contract.owner(), HOSTIO_SENDER);
+        assert_eq!(contract.pending_owner(), Address::ZERO);
+
+        // Renouncing clears any pending nomination as well
+        contract.transfer_ownership(alice).unwrap();
+        contract.renounce_ownership().unwrap();
+        assert_eq!(contract.owner(), Address::ZERO);
+        assert_eq!(contract.pending_owner(), Address::ZERO);
+        assert!(matches!(
+            contract.transfer_ownership(alice).unwrap_err(),
+            Error::UnauthorizedAccount(_)
+        ));
+
+        // The treasurer can no longer withdraw to the zero owner, only to a chosen wallet
+        vm.set_balance(vm.contract_address(), U256::from(100));
+        assert!(matches!(
+            contract.withdraw().unwrap_err(),
+            Error::InvalidOwner(_)
+        ));
+        assert_eq!(vm.balance(Address::ZERO), U256::ZERO);
+        contract.withdraw_to(alice, U256::from(100)).unwrap();
+        assert_eq!(vm.balance(alice), U256::from(100));
+    }
+
+    #[test]
+    fn test_withdraw_to_and_erc20() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+        let _ = contract.constructor(HOSTIO_SENDER);
+
+        let alice = Address::from([2u8; 20]);
+        let bob = Address::from([3u8; 20]);
+        let token = Address::from([9u8; 20]);
+        vm.set_balance(contract.vm().contract_address(), U256::from(100));
+
+        // Exact amounts go to the chosen wallet
+        contract.withdraw_to(alice, U256::from(30)).unwrap();
+        assert_eq!(vm.balance(alice), U256::from(30));
+        assert_eq!(vm.balance(contract.vm().contract_address()), U256::from(70));
+
+        // More than the balance, or a recipient that cannot be paid, reverts
+        let err = contract.withdraw_to(alice, U256::from(71)).unwrap_err();
+        assert!(matches!(err, Error::InsufficientAvailableBalance(_)));
+        vm.set_balance(bob, U256::MAX);
+        let err = contract.withdraw_to(bob, U256::from(1)).unwrap_err();
+        assert!(matches!(err, Error::WithdrawFailed(_)));
+
+        // Treasury funds cannot be burnt or sent back to the contract itself
+        let err = contract
+            .withdraw_to(Address::ZERO, U256::from(1))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidWithdrawRecipient(InvalidWithdrawRecipient { recipient })
+                if recipient.is_zero()
+        ));
+        let err = contract
+            .withdraw_to(vm.contract_address(), U256::from(1))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidWithdrawRecipient(InvalidWithdrawRecipient { recipient })
+                if recipient == vm.contract_address()
+        ));
+        let err = contract
+            .withdraw_erc20(token, Address::ZERO, U256::from(1))
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidWithdrawRecipient(_)));
+        assert_eq!(vm.balance(contract.vm().contract_address()), U256::from(70));
+
+        // A full withdraw to an owner that cannot be paid no longer succeeds silently
+        vm.set_balance(HOSTIO_SENDER, U256::MAX);
+        assert!(matches!(
+            contract.withdraw().unwrap_err(),
+            Error::WithdrawFailed(_)
+        ));
+        vm.set_balance(HOSTIO_SENDER, U256::ZERO);
+        contract.withdraw().unwrap();
+        assert_eq!(vm.balance(HOSTIO_SENDER), U256::from(70));
+
+        // Tokens are recovered with a plain `transfer`
+        let amount = U256::from(5);
+        let call_data = IERC20::transferCall { to: alice, amount }.abi_encode();
+        let err = contract.withdraw_erc20(token, alice, amount).unwrap_err();
+        assert!(matches!(err, Error::Erc20CallFailed(_)));
+        vm.set_code(token, vec![0x00]);
+        vm.mock_call(
+            token,
+            call_data.clone(),
+            Ok(U256::from(1).to_be_bytes_vec()),
+        );
+        contract.withdraw_erc20(token, alice, amount).unwrap();
+        vm.mock_call(token, call_data.clone(), Ok(U256::ZERO.to_be_bytes_vec()));
+        let err = contract.withdraw_erc20(token, alice, amount).unwrap_err();
+        assert!(matches!(err, Error::Erc20TransferReturnedFalse(_)));
+        vm.mock_call(token, call_data, Err(vec![]));
+        let err = contract.withdraw_erc20(token, alice, amount).unwrap_err();
+        assert!(matches!(err, Error::Erc20CallFailed(_)));
+
+        // Both entrypoints are reserved for the treasurer
+        contract
+            .revoke_role(TREASURER_ROLE.into(), HOSTIO_SENDER)
+            .unwrap();
+        assert!(matches!(
+            contract.withdraw_to(alice, U256::ZERO).unwrap_err(),
+            Error::AccessControlUnauthorizedAccount(_)
+        ));
+        assert!(matches!(
+            contract.withdraw_erc20(token, alice, amount).unwrap_err(),
+            Error::AccessControlUnauthorizedAccount(_)
+        ));
+    }
+
+    #[test]
+    fn test_protocol_fee() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+        let _ = contract.constructor(HOSTIO_SENDER);
+
+        let alice = Address::from([2u8; 20]);
+        let bob = Address::from([3u8; 20]);
+        let treasury = Address::from([4u8; 20]);
+        let token = Address::from([9u8; 20]);
+        vm.set_code(token, vec![0x00]);
+
+        // No fee until one is configured
+        assert_eq!(contract.quote_fee(U256::from(1_000)), U256::ZERO);
+
+        let err = contract
+            .set_fee(U256::from(MAX_FEE_BPS + 1), treasury)
+            .unwrap_err();
+        assert!(matches!(err, Error::FeeTooHigh(_)));
+        let err = contract
+            .set_fee(U256::from(100), Address::ZERO)
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidFeeRecipient(_)));
+        contract.set_fee(U256::from(100), treasury).unwrap();
+        assert_eq!(contract.fee_bps(), U256::from(100));
+        assert_eq!(contract.fee_recipient(), treasury);
+        assert_eq!(contract.quote_fee(U256::from(1_000)), U256::from(10));
+        assert_eq!(contract.quote_fee(U256::from(99)), U256::ZERO);
+        assert_eq!(contract.quote_fee(U256::MAX), U256::MAX / U256::from(100));
+
+        // The fee is charged on top of the amount sent
+        fund_call(&vm, U256::from(1_000));
+        let err = contract
+            .send_native_individual(alice, U256::from(1_000))
+            .unwrap_err();
+        assert!(matches!(err, Error::InsufficientValue(_)));
+        fund_call(&vm, U256::from(1_010));
+        contract
+            .send_native_individual(alice, U256::from(1_000))
+            .unwrap();
+        assert_eq!(vm.balance(alice), U256::from(1_000));
+        assert_eq!(contract.claimable(treasury), U256::from(10));
+        assert_eq!(
+            contract.get_user_native_sent(vm.msg_sender()),
+            U256::from(1_000)
+        );
+
+        // Best effort only pays the fee on transfers that landed
+        vm.set_balance(bob, U256::MAX);
+        fund_call(&vm, U256::from(2_020));
+        contract
+            .send_native_batch_best_effort(
+                vec![alice, bob],
+                vec![U256::from(1_000), U256::from(1_000)],
+            )
+            .unwrap();
+        assert_eq!(contract.claimable(treasury), U256::from(20));
+        assert_eq!(contract.get_fees_collected(Address::ZERO), U256::from(20));
+
+        // Native fees accrue to the fee recipient, who pulls them even if it refused a push
+        assert_eq!(contract.get_escrowed(Address::ZERO), U256::from(20));
+        vm.set_balance(treasury, U256::MAX);
+        fund_call(&vm, U256::from(1_010));
+        contract
+            .send_native_individual(alice, U256::from(1_000))
+            .unwrap();
+        vm.set_balance(treasury, U256::ZERO);
+        let sender = vm.msg_sender();
+        vm.set_sender(treasury);
+        vm.set_value(U256::ZERO);
+        contract.claim().unwrap();
+        vm.set_sender(sender);
+        assert_eq!(vm.balance(treasury), U256::from(30));
+        assert_eq!(contract.get_escrowed(Address::ZERO), U256::ZERO);
+
+        // ERC-20 fees are pulled from the sender per token
+        vm.set_value(U256::ZERO);
+        contract
+            .send_erc20_batch(
+                token,
+                vec![alice, alice],
+                vec![U256::from(500), U256::from(500)],
+            )
+            .unwrap();
+        assert_eq!(contract.get_fees_collected(token), U256::from(10));
+        assert_eq!(contract.get_total_sent_for_token(token), U256::from(1_000));
+
+        // A rejected fee pull reverts the send
+        let fee_call = IERC20::transferFromCall {
+            from: vm.msg_sender(),
+            to: treasury,
+            amount: U256::from(10),
+        }
+        .abi_encode();
+        vm.mock_call(token, fee_call, Ok(U256::ZERO.to_be_bytes_vec()));
+        let err = contract
+            .send_erc20_individual(token, alice, U256::from(1_000))
+            .unwrap_err();
+        assert!(matches!(err, Error::Erc20TransferReturnedFalse(_)));
+
+        // Turning the fee off needs no recipient
+        contract.set_fee(U256::ZERO, Address::ZERO).unwrap();
+        assert_eq!(contract.quote_fee(U256::from(1_000)), U256::ZERO);
+    }
+
+    #[test]
+    fn test_fee_exemptions_and_tiers() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+        let _ = contract.constructor(HOSTIO_SENDER);
+
+        let alice = Address::from([2u8; 20]);
+        let treasury = Address::from([4u8; 20]);
+        let token = Address::from([9u8; 20]);
+        vm.set_code(token, vec![0x00]);
+        let sender = vm.msg_sender();
+        contract.set_fee(U256::from(100), treasury).unwrap();
+
+        // Tiers must be ascending, paired and within the fee cap
+        let err = contract
+            .set_fee_tiers(Address::ZERO, vec![U256::from(1_000)], vec![])
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidFeeTiers(_)));
+        let err = contract
+            .set_fee_tiers(
+                Address::ZERO,
+                vec![U256::from(1_000), U256::from(1_000)],
+                vec![U256::from(50), U256::from(25)],
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidFeeTiers(_)));
+        let err = contract
+            .set_fee_tiers(
+                Address::ZERO,
+                vec![U256::from(1_000)],
+                vec![U256::from(MAX_FEE_BPS + 1)],
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::FeeTooHigh(_)));
+        let err = contract
+            .set_fee_tiers(
+                Address::ZERO,
+                vec![U256::from(1_000), U256::from(5_000)],
+                vec![U256::from(25), U256::from(50)],
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidFeeTiers(_)));
+
+        let min_volumes = vec![U256::from(1_000), U256::from(5_000)];
+        let tier_bps = vec![U256::from(50), U256::from(25)];
+        contract
+            .set_fee_tiers(Address::ZERO, min_volumes.clone(), tier_bps.clone())
+            .unwrap();
+        assert_eq!(contract.fee_tiers(Address::ZERO), (min_volumes, tier_bps));
+        assert_eq!(contract.fee_tiers(token), (vec![], vec![]));
+
+        // Below the first tier the base rate applies, tiers kick in from the next send
+        assert_eq!(
+            contract.effective_fee_bps(sender, Address::ZERO),
+            U256::from(100)
+        );
+        fund_call(&vm, U256::from(1_010));
+        contract
+            .send_native_individual(alice, U256::from(1_000))
+            .unwrap();
+        assert_eq!(
+            contract.effective_fee_bps(sender, Address::ZERO),
+            U256::from(50)
+        );
+        assert_eq!(
+            contract.quote_fee_for(sender, Address::ZERO, U256::from(4_000)),
+            U256::from(20)
+        );
+        fund_call(&vm, U256::from(4_020));
+        contract
+            .send_native_individual(alice, U256::from(4_000))
+            .unwrap();
+        assert_eq!(
+            contract.effective_fee_bps(sender, Address::ZERO),
+            U256::from(25)
+        );
+        assert_eq!(contract.claimable(treasury), U256::from(30));
+
+        // Volume is tracked per token, native tiers do not discount ERC-20 sends
+        assert_eq!(contract.effective_fee_bps(sender, token), U256::from(100));
+        assert_eq!(contract.quote_fee(U256::from(1_000)), U256::from(10));
+
+        // Exempt senders pay nothing at all
+        contract.set_fee_exempt(sender, true).unwrap();
+        assert!(contract.is_fee_exempt(sender));
+        assert_eq!(contract.effective_fee_bps(sender, token), U256::ZERO);
+        vm.set_value(U256::ZERO);
+        contract
+            .send_erc20_individual(token, alice, U256::from(1_000))
+            .unwrap();
+        assert_eq!(contract.get_fees_collected(token), U256::ZERO);
+
+        contract.set_fee_exempt(sender, false).unwrap();
+        assert_eq!(contract.effective_fee_bps(sender, token), U256::from(100));
+
+        // Clearing the tiers restores the base rate
+        contract
+            .set_fee_tiers(Address::ZERO, vec![], vec![])
+            .unwrap();
+        assert_eq!(
+            contract.effective_fee_bps(sender, Address::ZERO),
+            U256::from(100)
+        );
+
+        // Tiers are capped at the base rate and cannot charge anything without a recipient
+        contract
+            .set_fee_tiers(token, vec![U256::ZERO], vec![U256::from(MAX_FEE_BPS)])
+            .unwrap();
+        assert_eq!(contract.effective_fee_bps(sender, token), U256::from(100));
+        contract.set_fee(U256::ZERO, Address::ZERO).unwrap();
+        assert_eq!(contract.effective_fee_bps(sender, token), U256::ZERO);
+        fund_call(&vm, U256::from(100));
+        contract
+            .set_fee_tiers(
+                Address::ZERO,
+                vec![U256::ZERO],
+                vec![U256::from(MAX_FEE_BPS)],
+            )
+            .unwrap();
+        contract
+            .send_native_individual(alice, U256::from(100))
+            .unwrap();
+        assert_eq!(vm.balance(Address::ZERO), U256::ZERO);
+        assert_eq!(contract.get_fees_collected(Address::ZERO), U256::from(30));
+    }
+
+    #[test]
+    fn test_max_batch_size() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+        let _ = contract.constructor(HOSTIO_SENDER);
+
+        let alice = Address::from([2u8; 20]);
+        let token = Address::from([9u8; 20]);
+        vm.set_code(token, vec![0x00]);
+        assert_eq!(
+            contract.max_batch_size(),
+            U256::from(DEFAULT_MAX_BATCH_SIZE)
+        );
+
+        let err = contract.set_max_batch_size(U256::ZERO).unwrap_err();
+        assert!(matches!(err, Error::InvalidMaxBatchSize(_)));
+        contract.set_max_batch_size(U256::from(2)).unwrap();
+
+        // Batches up to the limit go through, anything above reverts before paying out
+        contract
+            .send_erc20_batch(token, vec![alice; 2], vec![U256::from(1); 2])
+            .unwrap();
+        let err = contract
+            .send_erc20_batch(token, vec![alice; 3], vec![U256::from(1); 3])
+            .unwrap_err();
+        assert!(matches!(err, Error::BatchTooLarge(_)));
+        fund_call(&vm, U256::from(3));
+        let err = contract
+            .send_native_batch(vec![alice; 3], vec![U256::from(1); 3])
+            .unwrap_err();
+        assert!(matches!(err, Error::BatchTooLarge(_)));
+        assert_eq!(vm.balance(alice), U256::ZERO);
+
+        // The gas estimate never exceeds the configured limit
+        assert_eq!(
+            contract.max_recipients_for_gas(U256::from(30_000_000), token),
+            U256::from(2)
+        );
+        contract
+            .set_max_batch_size(U256::from(DEFAULT_MAX_BATCH_SIZE))
+            .unwrap();
+        assert_eq!(
+            contract.max_recipients_for_gas(U256::from(BATCH_BASE_GAS), Address::ZERO),
+            U256::ZERO
+        );
+        let native_gas = NATIVE_TRANSFER_GAS + CALLDATA_GAS_PER_RECIPIENT;
+        let erc20_gas = ERC20_TRANSFER_GAS + CALLDATA_GAS_PER_RECIPIENT;
+        assert_eq!(
+            contract.max_recipients_for_gas(
+                U256::from(BATCH_BASE_GAS + 10 * native_gas),
+                Address::ZERO
+            ),
+            U256::from(10)
+        );
+        assert_eq!(
+            contract.max_recipients_for_gas(U256::from(BATCH_BASE_GAS + 10 * erc20_gas - 1), token),
+            U256::from(9)
+        );
+
+        // A configured fee reserves room for one more transfer, unless the token's tiers waive it
+        contract
+            .set_fee(U256::from(100), Address::from([4u8; 20]))
+            .unwrap();
+        assert_eq!(
+            contract.max_recipients_for_gas(
+                U256::from(BATCH_BASE_GAS + 10 * native_gas),
+                Address::ZERO
+            ),
+            U256::from(9)
+        );
+        contract
+            .set_fee_tiers(token, vec![U256::ZERO], vec![U256::ZERO])
+            .unwrap();
+        assert_eq!(
+            contract.max_recipients_for_gas(U256::from(BATCH_BASE_GAS + 10 * erc20_gas), token),
+            U256::from(10)
+        );
+        assert_eq!(
+            contract.max_recipients_for_gas(U256::MAX, token),
+            U256::from(DEFAULT_MAX_BATCH_SIZE)
+        );
+    }
+
+    #[test]
+    fn test_duplicate_recipients() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+        let _ = contract.constructor(HOSTIO_SENDER);
+
+        let alice = Address::from([2u8; 20]);
+        let bob = Address::from([3u8; 20]);
+        let carol = Address::from([4u8; 20]);
+        let token = Address::from([9u8; 20]);
+        vm.set_code(token, vec![0x00]);
+
+        assert!(contract.find_duplicates(vec![]).is_empty());
+        assert!(contract.find_duplicates(vec![alice, bob, carol]).is_empty());
+        assert_eq!(
+            contract.find_duplicates(vec![bob, alice, bob, carol, alice, bob]),
+            vec![U256::from(2), U256::from(4), U256::from(5)]
+        );
+
+        // The first repeat is reported and nothing is paid out
+        fund_call(&vm, U256::from(30));
+        let err = contract
+            .send_native_batch_unique(vec![alice, bob, alice], vec![U256::from(10); 3])
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::DuplicateRecipient(DuplicateRecipient { index }) if index == U256::from(2)
+        ));
+        assert_eq!(vm.balance(alice), U256::ZERO);
+        contract
+            .send_native_batch_unique(vec![alice, bob, carol], vec![U256::from(10); 3])
+            .unwrap();
+        assert_eq!(vm.balance(alice), U256::from(10));
+
+        // Failed transfers are credited like in `send_native_batch` rather than reverting
+        vm.set_balance(carol, U256::MAX);
+        fund_call(&vm, U256::from(20));
+        contract
+            .send_native_batch_unique(vec![alice, carol], vec![U256::from(10); 2])
+            .unwrap();
+        assert_eq!(vm.balance(alice), U256::from(20));
+        assert_eq!(contract.claimable(carol), U256::from(10));
+        vm.set_balance(carol, U256::ZERO);
+
+        // Oversized batches are rejected before looking for duplicates
+        contract.set_max_batch_size(U256::from(2)).unwrap();
+        fund_call(&vm, U256::from(30));
+        let err = contract
+            .send_native_batch_unique(vec![alice; 3], vec![U256::from(10); 3])
+            .unwrap_err();
+        assert!(matches!(err, Error::BatchTooLarge(_)));
+        contract
+            .set_max_batch_size(U256::from(DEFAULT_MAX_BATCH_SIZE))
+            .unwrap();
+
+        vm.set_value(U256::ZERO);
+        let err = contract
+            .send_erc20_batch_unique(token, vec![carol, carol], vec![U256::from(1); 2])
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::DuplicateRecipient(DuplicateRecipient { index }) if index == U256::from(1)
+        ));
+        contract
+            .send_erc20_batch_unique(token, vec![alice, carol], vec![U256::from(1); 2])
+            .unwrap();
+        assert_eq!(contract.get_total_sent_for_token(token), U256::from(2));
+
+        // The plain batch entrypoints keep accepting repeats
+        contract
+            .send_erc20_batch(token, vec![carol, carol], vec![U256::from(1); 2])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_invalid_recipients() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+        let _ = contract.constructor(HOSTIO_SENDER);
+        let alice = Address::from([2u8; 20]);
+        let token = Address::from([9u8; 20]);
+        vm.set_code(token, vec![0x00]);
+        let this = vm.contract_address();
+
+        // Single sends report index zero
+        fund_call(&vm, U256::from(10));
+        let err = contract
+            .send_native_individual(Address::ZERO, U256::from(10))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::ZeroRecipient(ZeroRecipient { index }) if index.is_zero()
+        ));
+        let err = contract
+            .send_native_individual(this, U256::from(10))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::SelfRecipient(SelfRecipient { index }) if index.is_zero()
+        ));
+
+        // Batches report the position of the offending entry
+        fund_call(&vm, U256::from(20));
+        let err = contract
+            .send_native_batch(vec![alice, Address::ZERO], vec![U256::from(10); 2])
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::ZeroRecipient(ZeroRecipient { index }) if index == U256::from(1)
+        ));
+        let err = contract
+            .send_native_batch(vec![this, alice], vec![U256::from(10); 2])
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::SelfRecipient(SelfRecipient { index }) if index.is_zero()
+        ));
+        assert_eq!(vm.balance(alice), U256::ZERO);
+        assert_eq!(contract.get_total_native_sent(), U256::ZERO);
+
+        vm.set_value(U256::ZERO);
+        let err = contract
+            .send_erc20_individual(token, Address::ZERO, U256::from(10))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::ZeroRecipient(ZeroRecipient { index }) if index.is_zero()
+        ));
+        let err = contract
+            .send_erc20_individual(token, this, U256::from(10))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::SelfRecipient(SelfRecipient { index }) if index.is_zero()
+        ));
+
+        let err = contract
+            .send_erc20_batch(
+                token,
+                vec![alice, alice, Address::ZERO],
+                vec![U256::from(1); 3],
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::ZeroRecipient(ZeroRecipient { index }) if index == U256::from(2)
+        ));
+        let err = contract
+            .send_erc20_batch(token, vec![alice, this], vec![U256::from(1); 2])
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::SelfRecipient(SelfRecipient { index }) if index == U256::from(1)
+        ));
+        assert_eq!(contract.get_total_sent_for_token(token), U256::ZERO);
+    }
+
+    #[test]
+    fn test_claimable_fallback() {
+        let vm = TestVM::default();
+        let mut contract = YourContract::from(&vm);
+        let _ = contract.constructor(HOSTIO_SENDER);
+
+        let alice = Address::from([2u8; 20]);
+        let bob = Address::from([3u8; 20]);
+        let sender = vm.msg_sender();
+
+        // Bob cannot receive, his share is credited instead of lost or reverted
+        vm.set_balance(bob, U256::MAX);
+        fund_call(&vm, U256::from(30));
+        contract
+            .send_native_batch(vec![alice, bob], vec![U256::from(10), U256::from(20)])
+            .unwrap();
+        assert_eq!(vm.balance(alice), U256::from(10));
+        assert_eq!(contract.claimable(bob), U256::from(20));
+        assert_eq!(contract.claimable(alice), U256::ZERO);
+        assert_eq!(contract.get_escrowed(Address::ZERO), U256::from(20));
+        assert_eq!(contract.get_user_native_sent(sender), U256::from(30));
+
+        fund_call(&vm, U256::from(5));
+        contract.send_native_individual(bob, U256::from(5)).unwrap();
+        assert_eq!(contract.claimable(bob), U256::from(25));
+
+        // Credited funds cannot be swept by the treasurer
+        vm.set_value(U256::ZERO);
+        contract.withdraw().unwrap();
+        assert_eq!(vm.balance(vm.contract_address()), U256::from(25));
+
+        let err = contract.claim().unwrap_err();
+        assert!(matches!(err, Error::NothingToClaim(_)));
+
+        // Once able to receive, the recipient pulls the credit exactly once
+        vm.set_sender(bob);
+        vm.set_balance(bob, U256::ZERO);
+        contract.claim().unwrap();
+        assert_eq!(vm.balance(bob), U256::from(25));
+        assert_eq!(contract.claimable(bob), U256::ZERO);
+        assert_eq!(contract.get_escrowed(Address::ZERO), U256::ZERO);
+        let err = contract.claim().unwrap_err();
+        assert!(matches!(err, Error::NothingToClaim(_)));
     }
 }